@@ -0,0 +1,509 @@
+use crate::session_commands;
+use crate::settings_profile;
+use f90x_tool::camera_interface::models::CameraProfile;
+use f90x_tool::shooting_data;
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Range;
+
+/// How the bytes of a memory location are decoded.
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// Raw bytes, shown in hex.
+    #[default]
+    Bytes,
+    U8,
+    LeU16,
+    /// Binary coded decimal, least significant byte first.
+    Bcd,
+    /// Named bits, the values are the masks.
+    Bitflags,
+    /// Named values.
+    Enum,
+}
+
+/// Whether a memory location can be written.
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    /// Writing needs a confirmation.
+    #[default]
+    Unknown,
+    /// Known to be safe to write.
+    Safe,
+    /// Never written.
+    Protected,
+}
+
+/// A named memory location.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AddressEntry {
+    pub name: String,
+    pub address: u16,
+    /// Number of bytes.
+    #[serde(default = "default_size")]
+    pub size: u16,
+    /// Memory space of the address.
+    #[serde(default)]
+    pub space: u8,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Names of the values for the "enum" encoding, or of the bit masks for "bitflags".
+    #[serde(default)]
+    pub values: BTreeMap<String, u8>,
+    #[serde(default)]
+    pub access: Access,
+    /// Whether the location is a setting, that is included in backups.
+    #[serde(default)]
+    pub backup: bool,
+    #[serde(default)]
+    pub description: String,
+}
+
+fn default_size() -> u16 {
+    return 1;
+}
+
+fn decode_bcd(bytes: &[u8]) -> Option<u32> {
+    let mut number: u32 = 0;
+    for byte in bytes.iter().rev() {
+        let (high, low) = (byte >> 4, byte & 0x0F);
+        if high > 9 || low > 9 {
+            return None;
+        }
+        number = number * 100 + (high as u32) * 10 + (low as u32);
+    }
+    return Some(number);
+}
+
+impl AddressEntry {
+    pub fn get_address_range(&self) -> Range<usize> {
+        return (self.address as usize)..(self.address as usize) + (self.size as usize);
+    }
+
+    fn validate(&self) -> Result<()> {
+        let expected_size = match self.encoding {
+            Encoding::Bytes => 1..=u16::MAX,
+            Encoding::U8 | Encoding::Bitflags | Encoding::Enum => 1..=1,
+            Encoding::LeU16 => 2..=2,
+            Encoding::Bcd => 1..=4,
+        };
+        if !expected_size.contains(&self.size) {
+            return Err(anyhow!("\"{}\" has a wrong size for the {:?} encoding", self.name, self.encoding));
+        }
+        let has_values = matches!(self.encoding, Encoding::Bitflags | Encoding::Enum);
+        if has_values == self.values.is_empty() {
+            return Err(anyhow!("\"{}\" should have values only with the enum or bitflags encoding", self.name));
+        }
+        if self.backup && self.space != 0 {
+            return Err(anyhow!("\"{}\" can't be backed up, only the \"0\" memory space can be restored", self.name));
+        }
+        if self.get_address_range().end > 0x10000 {
+            return Err(anyhow!("\"{}\" is out of the address space", self.name));
+        }
+        return Ok(());
+    }
+
+    /// Decodes the bytes of the location to a readable value.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        if bytes.len() != self.size as usize {
+            return format!("{:02X?}", bytes);
+        }
+        match self.encoding {
+            Encoding::Bytes => return format!("{:02X?}", bytes),
+            Encoding::U8 => return format!("{} (0x{:02X})", bytes[0], bytes[0]),
+            Encoding::LeU16 => {
+                let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+                return format!("0x{:04X} ({})", value, value);
+            },
+            Encoding::Bcd => return decode_bcd(bytes).map_or(format!("invalid BCD {:02X?}", bytes), |number| number.to_string()),
+            Encoding::Enum => {
+                let name = self.values.iter().find(|(_, value)| **value == bytes[0]).map(|(name, _)| name.as_str());
+                return format!("{} (0x{:02X})", name.unwrap_or("unknown"), bytes[0]);
+            },
+            Encoding::Bitflags => {
+                let mut names: Vec<&str> = self.values.iter()
+                        .filter(|(_, mask)| bytes[0] & **mask != 0)
+                        .map(|(name, _)| name.as_str())
+                        .collect();
+                if names.is_empty() {
+                    names.push("none");
+                }
+                return format!("{} (0x{:02X})", names.join(" | "), bytes[0]);
+            },
+        }
+    }
+
+    /// Returns the byte of a named value, for the "enum" encoding.
+    pub fn encode_name(&self, name: &str) -> Option<u8> {
+        if self.encoding != Encoding::Enum {
+            return None;
+        }
+        return self.values.get(name).copied();
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AddressMapFile {
+    #[serde(default)]
+    address: Vec<AddressEntry>,
+}
+
+fn le_u16_entry(name: &str, address: u16, access: Access, description: &str) -> AddressEntry {
+    return AddressEntry {
+        name: name.to_string(),
+        address,
+        size: 2,
+        space: 0,
+        encoding: Encoding::LeU16,
+        values: BTreeMap::new(),
+        access,
+        backup: false,
+        description: description.to_string(),
+    };
+}
+
+/// Returns the locations of the memory map of the camera profile, so that the addresses are only
+/// given in the profile.
+fn get_profile_entries(profile: &CameraProfile) -> Vec<AddressEntry> {
+    let memory_map = &profile.memory_map;
+    let memo_holder_setting = AddressEntry {
+        name: String::from("memo.setting"),
+        address: memory_map.memo_holder_setting,
+        size: 1,
+        space: 0,
+        encoding: Encoding::Enum,
+        values: shooting_data::MEMO_HOLDER_SETTING_VALUES.iter()
+            .map(|(setting, value)| (settings_profile::to_snake_case(setting), *value))
+            .collect(),
+        access: Access::Safe,
+        backup: true,
+        description: String::from("Memo holder setting. Values without the 0x40 flag mean that nothing is stored"),
+    };
+    return vec![
+        le_u16_entry("ring_buffer.start", memory_map.ring_buffer_addresses, Access::Protected,
+                     "Start address of the memo holder ring buffer"),
+        le_u16_entry("ring_buffer.end", memory_map.ring_buffer_addresses + 2, Access::Protected,
+                     "End address of the memo holder ring buffer"),
+        memo_holder_setting,
+        le_u16_entry("memo.current", memory_map.memo_holder_addresses, Access::Unknown,
+                     "Address that the memo holder writes the next frame to"),
+        le_u16_entry("memo.start", memory_map.memo_holder_addresses + 2, Access::Unknown,
+                     "Start address of the shooting data in the ring buffer"),
+        le_u16_entry("memo.current_roll_start", memory_map.memo_holder_addresses + 4, Access::Unknown,
+                     "Start address of the shooting data of the current roll"),
+    ];
+}
+
+/// An address given on the command line, as a number or as a name from the address map.
+pub struct ResolvedAddress<'a> {
+    pub address: u16,
+    pub entry: Option<&'a AddressEntry>,
+}
+
+impl ResolvedAddress<'_> {
+    /// Returns the number of bytes of a named location, or 1 for a number.
+    pub fn get_size(&self) -> usize {
+        return self.entry.map_or(1, |entry| entry.size as usize);
+    }
+
+    /// Returns the number of bytes to read at once: the size of a named location, or 1.
+    pub fn get_read_length(&self) -> Result<u8> {
+        return u8::try_from(self.get_size()).map_err(|_| {
+            anyhow!("\"{}\" is too large to read at once, use the dump command", self.entry.map_or("", |entry| entry.name.as_str()))
+        });
+    }
+
+    /// Returns the memory space of a named location, or the "0" memory space for a number.
+    pub fn get_memory_space(&self) -> u8 {
+        return self.entry.map_or(0, |entry| entry.space);
+    }
+}
+
+/// Names of memory locations, read from a TOML file with an `[[address]]` table per location:
+///
+/// ```toml
+/// [[address]]
+/// name = "memo.setting"
+/// address = 0xFD40
+/// size = 1
+/// space = 0
+/// encoding = "enum"    # bytes (default), u8, le_u16, bcd, bitflags or enum
+/// values = { minimum = 0x45, intermediate = 0x4E, full = 0x5F }
+/// access = "safe"      # unknown (default), safe or protected
+/// backup = true        # included in backups
+/// description = "Memo holder setting"
+/// ```
+///
+/// The known locations of a camera model come from the memory map of its profile, see
+/// [AddressMap::load_with_defaults].
+#[derive(Debug, Default)]
+pub struct AddressMap {
+    entries: Vec<AddressEntry>,
+    /// Model whose known locations are in the map, if any.
+    model_name: Option<&'static str>,
+}
+
+impl AddressMap {
+    pub fn parse(text: &str) -> Result<AddressMap> {
+        let file: AddressMapFile = toml::from_str(text)?;
+        for entry in &file.address {
+            entry.validate()?;
+        }
+        return Ok(AddressMap { entries: file.address, model_name: None });
+    }
+
+    pub fn load(path: &str) -> Result<AddressMap> {
+        let text = fs::read_to_string(path).with_context(|| format!("Could not read the address map {}", path))?;
+        return AddressMap::parse(&text).with_context(|| format!("Invalid address map {}", path));
+    }
+
+    /// Returns the known locations of the camera model, with the ones from the given file added.
+    /// The entries of the file replace the known ones with the same name.
+    pub fn load_with_defaults(path: Option<&String>, profile: &CameraProfile) -> Result<AddressMap> {
+        let mut address_map = AddressMap { entries: get_profile_entries(profile), model_name: Some(profile.model_name) };
+        if let Some(path) = path {
+            for entry in AddressMap::load(path)?.entries {
+                address_map.entries.retain(|known_entry| known_entry.name != entry.name);
+                address_map.entries.push(entry);
+            }
+        }
+        return Ok(address_map);
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<&AddressEntry> {
+        return self.entries.iter().find(|entry| entry.name == name);
+    }
+
+    /// Returns the location that starts at the given address, if any.
+    pub fn find_by_address(&self, memory_space: u8, address: u16) -> Option<&AddressEntry> {
+        return self.entries.iter().find(|entry| entry.space == memory_space && entry.address == address);
+    }
+
+    /// Resolves an address that is given as a number, or as a name from the map.
+    pub fn resolve(&self, value: &str) -> Result<ResolvedAddress<'_>> {
+        if let Ok(address) = session_commands::parse_number::<u16>(value) {
+            return Ok(ResolvedAddress { address, entry: None });
+        }
+        let entry = self.find_by_name(value)
+                .ok_or(anyhow!("Unknown address \"{}\". Use a number or a name from the address map.", value))?;
+        return Ok(ResolvedAddress { address: entry.address, entry: Some(entry) });
+    }
+
+    /// Resolves an address in the given memory space. Fails if the name is of a location in
+    /// another memory space.
+    pub fn resolve_in_space(&self, value: &str, memory_space: u8) -> Result<ResolvedAddress<'_>> {
+        let resolved = self.resolve(value)?;
+        if let Some(entry) = resolved.entry.filter(|entry| entry.space != memory_space) {
+            return Err(anyhow!("\"{}\" is in memory space {}, not in memory space {}", entry.name, entry.space, memory_space));
+        }
+        return Ok(resolved);
+    }
+
+    /// Parses the values to write to the given address. With a name, the values can also be the
+    /// names of the "enum" values of the location.
+    pub fn parse_values<S: AsRef<str>>(&self, resolved: &ResolvedAddress, values: &[S]) -> Result<Vec<u8>> {
+        return values.iter()
+                .map(|value| {
+                    if let Some(byte) = resolved.entry.and_then(|entry| entry.encode_name(value.as_ref())) {
+                        return Ok(byte);
+                    }
+                    return session_commands::parse_number::<u8>(value.as_ref());
+                })
+                .collect();
+    }
+
+    /// Returns the locations that are included in backups.
+    pub fn get_backup_entries(&self) -> Vec<&AddressEntry> {
+        return self.entries.iter().filter(|entry| entry.backup).collect();
+    }
+
+    /// Fails if the map has the known locations of another model than the given one.
+    pub fn check_model(&self, model_name: &str) -> Result<()> {
+        if let Some(map_model_name) = self.model_name.filter(|map_model_name| *map_model_name != model_name) {
+            return Err(anyhow!("The address map is for the {}, but the camera is a {}", map_model_name, model_name));
+        }
+        return Ok(());
+    }
+
+    /// Checks a write to the given range of the "0" memory space. Fails if the range overlaps with
+    /// a protected location. Returns true if every byte of the range is in a location that is
+    /// known to be safe to write.
+    pub fn check_write(&self, range: &Range<usize>) -> Result<bool> {
+        let entries = self.find_overlapping(0, range);
+        if let Some(entry) = entries.iter().find(|entry| entry.access == Access::Protected) {
+            return Err(anyhow!("Refusing to write to the protected location \"{}\" at {:04X}", entry.name, entry.address));
+        }
+        let is_safe = range.clone().all(|address| {
+            entries.iter().any(|entry| entry.access == Access::Safe && entry.get_address_range().contains(&address))
+        });
+        return Ok(is_safe);
+    }
+
+    /// Returns the locations that overlap with the given address range.
+    pub fn find_overlapping(&self, memory_space: u8, range: &Range<usize>) -> Vec<&AddressEntry> {
+        return self.entries.iter()
+                .filter(|entry| entry.space == memory_space)
+                .filter(|entry| {
+                    let entry_range = entry.get_address_range();
+                    entry_range.start < range.end && range.start < entry_range.end
+                })
+                .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use f90x_tool::camera_interface::models::{F90X_PROFILE, MemoryMap};
+
+    const TEST_MAP: &str = r#"
+        [[address]]
+        name = "memo.setting"
+        address = 0xFD40
+        description = "Memo holder setting"
+
+        [[address]]
+        name = "memo.current"
+        address = 0xFD42
+        size = 2
+        encoding = "le_u16"
+
+        [[address]]
+        name = "other.space"
+        address = 0xFD40
+        space = 1
+    "#;
+
+    fn entry(text: &str) -> AddressEntry {
+        return AddressMap::parse(text).unwrap().entries.remove(0);
+    }
+
+    #[test]
+    fn entries_should_have_defaults() {
+        let map = AddressMap::parse(TEST_MAP).unwrap();
+        let entry = &map.find_overlapping(0, &(0xFD40..0xFD41))[0];
+        assert_eq!("memo.setting", entry.name);
+        assert_eq!(1, entry.size);
+        assert_eq!(0, entry.space);
+        assert_eq!(Encoding::Bytes, entry.encoding);
+    }
+
+    #[test]
+    fn overlapping_entries_should_be_found_in_the_same_space() {
+        let map = AddressMap::parse(TEST_MAP).unwrap();
+        let names = |range: Range<usize>| -> Vec<String> {
+            map.find_overlapping(0, &range).iter().map(|entry| entry.name.clone()).collect()
+        };
+        assert_eq!(vec!["memo.setting", "memo.current"], names(0xFD40..0xFD44));
+        assert_eq!(vec!["memo.current"], names(0xFD43..0xFD50));
+        assert!(names(0xFD41..0xFD42).is_empty());
+    }
+
+    #[test]
+    fn invalid_entries_should_be_error() {
+        assert!(AddressMap::parse("[[address]]\nname = \"a\"\naddress = 1\nsise = 2\n").is_err());
+        assert!(AddressMap::parse("[[address]]\nname = \"a\"\naddress = 0x10000\n").is_err());
+        assert!(AddressMap::parse("[[address]]\nname = \"a\"\naddress = 1\nspace = 1\nbackup = true\n").is_err());
+        assert!(AddressMap::parse("[[address]]\nname = \"a\"\naddress = 1\nencoding = \"le_u16\"\n").is_err());
+        assert!(AddressMap::parse("[[address]]\nname = \"a\"\naddress = 1\nsize = 2\nencoding = \"u8\"\n").is_err());
+        assert!(AddressMap::parse("[[address]]\nname = \"a\"\naddress = 1\nencoding = \"enum\"\n").is_err());
+        assert!(AddressMap::parse("[[address]]\nname = \"a\"\naddress = 0xFFFF\nsize = 2\nencoding = \"bcd\"\n").is_err());
+    }
+
+    #[test]
+    fn values_should_be_decoded_by_encoding() {
+        let u8_entry = entry("[[address]]\nname = \"a\"\naddress = 1\nencoding = \"u8\"\n");
+        assert_eq!("69 (0x45)", u8_entry.decode(&[0x45]));
+
+        let u16_entry = entry("[[address]]\nname = \"a\"\naddress = 1\nsize = 2\nencoding = \"le_u16\"\n");
+        assert_eq!("0x13A6 (5030)", u16_entry.decode(&[0xA6, 0x13]));
+        assert_eq!("[A6]", u16_entry.decode(&[0xA6]));
+
+        let bcd_entry = entry("[[address]]\nname = \"a\"\naddress = 1\nsize = 2\nencoding = \"bcd\"\n");
+        assert_eq!("1337", bcd_entry.decode(&[0x37, 0x13]));
+        assert_eq!("invalid BCD [3A, 13]", bcd_entry.decode(&[0x3A, 0x13]));
+
+        let enum_entry = entry("[[address]]\nname = \"a\"\naddress = 1\nencoding = \"enum\"\nvalues = { minimum = 0x45, full = 0x5F }\n");
+        assert_eq!("full (0x5F)", enum_entry.decode(&[0x5F]));
+        assert_eq!("unknown (0x41)", enum_entry.decode(&[0x41]));
+        assert_eq!(Some(0x45), enum_entry.encode_name("minimum"));
+
+        let flags_entry = entry("[[address]]\nname = \"a\"\naddress = 1\nencoding = \"bitflags\"\nvalues = { on = 0x40, low = 0x01 }\n");
+        assert_eq!("low | on (0x41)", flags_entry.decode(&[0x41]));
+        assert_eq!("none (0x00)", flags_entry.decode(&[0x00]));
+    }
+
+    #[test]
+    fn writes_should_be_checked_against_the_access() {
+        let map = AddressMap::parse(r#"
+            [[address]]
+            name = "safe"
+            address = 0x10
+            size = 2
+            access = "safe"
+
+            [[address]]
+            name = "protected"
+            address = 0x20
+            access = "protected"
+        "#).unwrap();
+        assert!(map.check_write(&(0x10..0x12)).unwrap());
+        assert!(!map.check_write(&(0x10..0x13)).unwrap());
+        assert!(!map.check_write(&(0x30..0x31)).unwrap());
+        assert!(map.check_write(&(0x1F..0x21)).is_err());
+    }
+
+    #[test]
+    fn known_locations_should_come_from_the_profile() {
+        let profile = CameraProfile {
+            model_name: "F801S/N8008S",
+            memory_map: MemoryMap { ring_buffer_addresses: 0x1000, memo_holder_setting: 0x1010, memo_holder_addresses: 0x1020 },
+            ..F90X_PROFILE
+        };
+        let map = AddressMap::load_with_defaults(None, &profile).unwrap();
+        assert_eq!(0x1002, map.resolve("ring_buffer.end").unwrap().address);
+        assert_eq!(0x1010, map.resolve("memo.setting").unwrap().address);
+        assert_eq!(0x1024, map.resolve("memo.current_roll_start").unwrap().address);
+        assert!(map.check_model("F801S/N8008S").is_ok());
+        assert!(map.check_model("F90X/N90S").is_err());
+        assert!(AddressMap::parse(TEST_MAP).unwrap().check_model("F90X/N90S").is_ok());
+    }
+
+    #[test]
+    fn default_map_should_have_the_memo_holder_locations() {
+        let map = AddressMap::load_with_defaults(None, &F90X_PROFILE).unwrap();
+        assert_eq!(0xFD40, map.resolve("memo.setting").unwrap().address);
+        assert_eq!(0xFD46, map.resolve("memo.current_roll_start").unwrap().address);
+        assert_eq!("minimum (0x45)", map.find_by_name("memo.setting").unwrap().decode(&[0x45]));
+        assert!(map.resolve("0xFD40").unwrap().entry.is_none());
+        assert!(map.resolve("memo.unknown").is_err());
+        let backup_names: Vec<&str> = map.get_backup_entries().iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(vec!["memo.setting"], backup_names);
+
+        let resolved = map.resolve("memo.setting").unwrap();
+        assert_eq!(vec![0x5F], map.parse_values(&resolved, &[String::from("full")]).unwrap());
+        assert_eq!(vec![0x05], map.parse_values(&resolved, &[String::from("0x05")]).unwrap());
+        let resolved = map.resolve("0xFD40").unwrap();
+        assert!(map.parse_values(&resolved, &[String::from("full")]).is_err());
+    }
+
+    #[test]
+    fn names_should_be_resolved_in_their_memory_space() {
+        let map = AddressMap::load_with_defaults(None, &F90X_PROFILE).unwrap();
+        let resolved = map.resolve_in_space("memo.setting", 0).unwrap();
+        assert_eq!((0xFD40, 1, 1, 0), (resolved.address, resolved.get_size(), resolved.get_read_length().unwrap(), resolved.get_memory_space()));
+        assert_eq!(2, map.resolve_in_space("memo.current", 0).unwrap().get_read_length().unwrap());
+        assert!(map.resolve_in_space("memo.setting", 1).is_err());
+        assert_eq!(0xFD40, map.resolve_in_space("0xFD40", 1).unwrap().address);
+
+        let map = AddressMap::parse("[[address]]\nname = \"large\"\naddress = 0\nsize = 0x100\n").unwrap();
+        assert!(map.resolve("large").unwrap().get_read_length().is_err());
+    }
+}
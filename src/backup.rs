@@ -0,0 +1,204 @@
+use crate::address_map::AddressMap;
+use crate::cli_commands;
+use crate::journal::{self, Journal};
+use f90x_tool::camera_interface::{self, SerialCameraConnection};
+use f90x_tool::camera_operations;
+use f90x_tool::session::Session;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Values of one memory location of the "0" memory space.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BackupRegion {
+    pub name: String,
+    pub address: u16,
+    pub values: Vec<u8>,
+}
+
+/// The settings of a camera, as written to a backup file.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Backup {
+    pub model: String,
+    pub unit_code: String,
+    pub date: String,
+    pub regions: Vec<BackupRegion>,
+}
+
+/// A region that has other values in the camera than in the backup.
+#[derive(Debug, PartialEq)]
+pub struct RegionChange<'a> {
+    pub region: &'a BackupRegion,
+    pub current_values: Vec<u8>,
+}
+
+/// Returns the regions of the backup that differ from the current values, which are in the same
+/// order as the regions.
+pub fn get_changes(backup: &Backup, current_values: Vec<Vec<u8>>) -> Vec<RegionChange<'_>> {
+    return backup.regions.iter()
+            .zip(current_values)
+            .filter(|(region, current_values)| region.values != *current_values)
+            .map(|(region, current_values)| RegionChange { region, current_values })
+            .collect();
+}
+
+/// Formats a change, decoding the values if the address map has the same location.
+pub fn format_change(change: &RegionChange, address_map: &AddressMap) -> String {
+    let region = change.region;
+    let entry = address_map.find_by_address(0, region.address)
+            .filter(|entry| entry.size as usize == region.values.len());
+    let (current, restored) = match entry {
+        Some(entry) => (entry.decode(&change.current_values), entry.decode(&region.values)),
+        None => (format!("{:02X?}", change.current_values), format!("{:02X?}", region.values)),
+    };
+    return format!("{} ({:04X}): {} -> {}", region.name, region.address, current, restored);
+}
+
+fn read_region<T: camera_interface::CameraInterface>(session: &mut Session<T>, address: u16, length: usize) -> Result<Vec<u8>> {
+    return camera_operations::read_memory_range(session.get_camera()?, 0, address, length);
+}
+
+/// Reads the locations of the address map that are marked for backup, and writes them with the
+/// model of the camera to a JSON file.
+pub fn backup_to_file(serial_device: &String, address_map: &AddressMap, out_file: &String) -> Result<()> {
+    let entries = address_map.get_backup_entries();
+    if entries.is_empty() {
+        return Err(anyhow!("The address map has no locations to back up"));
+    }
+
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), true);
+    let camera_info = session.start()?.clone();
+    let mut regions: Vec<BackupRegion> = Vec::new();
+    for entry in &entries {
+        match read_region(&mut session, entry.address, entry.size as usize) {
+            Ok(values) => regions.push(BackupRegion { name: entry.name.clone(), address: entry.address, values }),
+            Err(error) => {
+                session.close()?;
+                return Err(error.context(format!("Could not read \"{}\"", entry.name)));
+            },
+        }
+    }
+    session.close()?;
+
+    let backup = Backup {
+        model: camera_info.model_name,
+        unit_code: camera_info.unit_code,
+        date: Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+        regions,
+    };
+    fs::write(out_file, serde_json::to_string_pretty(&backup)? + "\n")
+            .with_context(|| format!("Could not write {}", out_file))?;
+    println!("Backed up {} location(s) to {}", backup.regions.len(), out_file);
+    return Ok(());
+}
+
+/// Writes the values of a backup file to the camera. The model of the camera has to match the
+/// backup. The differences are shown and need a confirmation, unless `assume_yes` is set. The
+/// writes are verified and recorded in the journal.
+pub fn restore_from_file(
+        serial_device: &String,
+        address_map: &AddressMap,
+        journal: &Journal,
+        backup_file: &String,
+        assume_yes: bool) -> Result<()> {
+    let text = fs::read_to_string(backup_file).with_context(|| format!("Could not read {}", backup_file))?;
+    let backup: Backup = serde_json::from_str(&text).with_context(|| format!("Invalid backup file {}", backup_file))?;
+    for region in &backup.regions {
+        let range = (region.address as usize)..(region.address as usize) + region.values.len();
+        if range.is_empty() || range.end > 0x10000 {
+            return Err(anyhow!("Invalid range for \"{}\" in the backup", region.name));
+        }
+        address_map.check_write(&range)?;
+    }
+
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), true);
+    let result = restore(&mut session, address_map, journal, &backup, assume_yes);
+    session.close()?;
+    return result;
+}
+
+fn restore<T: camera_interface::CameraInterface>(
+        session: &mut Session<T>,
+        address_map: &AddressMap,
+        journal: &Journal,
+        backup: &Backup,
+        assume_yes: bool) -> Result<()> {
+    let model = session.start()?.model_name.clone();
+    if model != backup.model {
+        return Err(anyhow!("The backup is from a {}, but the camera is a {}", backup.model, model));
+    }
+
+    let mut current_values: Vec<Vec<u8>> = Vec::new();
+    for region in &backup.regions {
+        current_values.push(read_region(session, region.address, region.values.len())?);
+    }
+    let changes = get_changes(backup, current_values);
+    if changes.is_empty() {
+        println!("The camera already matches the backup.");
+        return Ok(());
+    }
+    for change in &changes {
+        println!("{}", format_change(change, address_map));
+    }
+    if !assume_yes && !cli_commands::confirm(&format!("Restore {} location(s)?", changes.len()))? {
+        return Err(anyhow!("Restore cancelled"));
+    }
+
+    for change in &changes {
+        let region = change.region;
+        for (index, chunk) in region.values.chunks(u8::MAX as usize).enumerate() {
+            let address = region.address + (index * (u8::MAX as usize)) as u16;
+            journal::write_with_journal(session, journal, address_map, address, chunk.to_vec())
+                    .with_context(|| format!("Could not restore \"{}\"", region.name))?;
+        }
+    }
+    println!("Restored and verified {} location(s).", changes.len());
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use f90x_tool::camera_interface::models::F90X_PROFILE;
+
+    fn region(name: &str, address: u16, values: Vec<u8>) -> BackupRegion {
+        return BackupRegion { name: name.to_string(), address, values };
+    }
+
+    fn test_backup() -> Backup {
+        return Backup {
+            model: String::from("F90X/N90S"),
+            unit_code: String::from("1020"),
+            date: String::from("2024-05-01T10:00:00+02:00"),
+            regions: vec![region("memo.setting", 0xFD40, vec![0x45]), region("custom", 0x1000, vec![0x01, 0x02])],
+        };
+    }
+
+    #[test]
+    fn only_the_differing_regions_should_be_changes() {
+        let backup = test_backup();
+        let changes = get_changes(&backup, vec![vec![0x45], vec![0x01, 0x03]]);
+        assert_eq!(vec![RegionChange { region: &backup.regions[1], current_values: vec![0x01, 0x03] }], changes);
+        assert!(get_changes(&backup, vec![vec![0x45], vec![0x01, 0x02]]).is_empty());
+    }
+
+    #[test]
+    fn changes_should_be_decoded_with_the_address_map() {
+        let backup = test_backup();
+        let address_map = AddressMap::load_with_defaults(None, &F90X_PROFILE).unwrap();
+        let changes = get_changes(&backup, vec![vec![0x5F], vec![0x00, 0x02]]);
+        assert_eq!("memo.setting (FD40): full (0x5F) -> minimum (0x45)", format_change(&changes[0], &address_map));
+        assert_eq!("custom (1000): [00, 02] -> [01, 02]", format_change(&changes[1], &address_map));
+    }
+
+    #[test]
+    fn backup_should_survive_a_round_trip() {
+        let backup = test_backup();
+        let text = serde_json::to_string_pretty(&backup).unwrap();
+        assert_eq!(backup, serde_json::from_str::<Backup>(&text).unwrap());
+    }
+}
@@ -0,0 +1,157 @@
+use crate::camera_interface::CameraInterface;
+use crate::camera_interface::models::ExposureCompensationSetting;
+use crate::camera_operations;
+
+use anyhow::{Context, Result, anyhow};
+use log::debug;
+
+#[cfg(test)]
+use mockall::{predicate::*, Sequence};
+
+/// Parses an exposure compensation in stops, like "+1", "-0.5" or "-2/3".
+pub fn parse_stops(value: &str) -> Result<f64> {
+    let invalid = || anyhow!("Invalid stops \"{}\"", value);
+    let stops = match value.split_once('/') {
+        Some((numerator, denominator)) => {
+            let numerator: f64 = numerator.parse().map_err(|_| invalid())?;
+            let denominator: f64 = denominator.parse().map_err(|_| invalid())?;
+            numerator / denominator
+        },
+        None => value.parse().map_err(|_| invalid())?,
+    };
+    if !stops.is_finite() {
+        return Err(invalid());
+    }
+    return Ok(stops);
+}
+
+fn shoot_frames<T: CameraInterface>(
+        camera: &mut T,
+        setting: &ExposureCompensationSetting,
+        values: &[(f64, u8)],
+        on_release: &mut impl FnMut(f64)) -> Result<()> {
+    for (stops, value) in values {
+        camera_operations::write_memory_verified(camera, setting.address, vec![*value]).with_context(|| format!("Could not set {:+} stops", stops))?;
+        camera_operations::release_shutter(camera).with_context(|| format!("Could not release at {:+} stops", stops))?;
+        on_release(*stops);
+    }
+    return Ok(());
+}
+
+/// Releases the shutter once for each given exposure compensation, in stops.
+///
+/// Each compensation is written to the camera and read back before the release. The original
+/// compensation is restored at the end, also when a frame fails. `on_release` is called after
+/// each release.
+pub fn shoot_bracket<T: CameraInterface>(
+        camera: &mut T,
+        setting: &ExposureCompensationSetting,
+        stops: &[f64],
+        mut on_release: impl FnMut(f64)) -> Result<()> {
+    // Check all the values first, so that nothing is written if one of them is invalid.
+    let values = stops.iter()
+            .map(|stops| Ok((*stops, setting.stops_to_value(*stops)?)))
+            .collect::<Result<Vec<(f64, u8)>>>()?;
+
+    let original_value = camera_operations::read_memory(camera, 0, setting.address, 1)?[0];
+    debug!("Original exposure compensation value: {:02X}", original_value);
+    let result = shoot_frames(camera, setting, &values, &mut on_release);
+    let restore_result = camera_operations::write_memory_verified(camera, setting.address, vec![original_value])
+            .context("Could not restore the original exposure compensation");
+    return result.and(restore_result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_interface::MockCameraInterface;
+    use crate::camera_interface::messaging::{CameraCommand, DataPacket};
+
+    const SETTING: ExposureCompensationSetting = ExposureCompensationSetting { address: 0x1234, steps_per_stop: 3 };
+
+    fn expect_read(mock_camera: &mut MockCameraInterface, sequence: &mut Sequence, value: u8) {
+        mock_camera.expect_send_command()
+                   .with(eq(CameraCommand::ReadMemory { memory_space: 0, address: 0x1234, length: 1 }))
+                   .times(1)
+                   .in_sequence(sequence)
+                   .returning(|_| Ok(()));
+        mock_camera.expect_expect_data_packet()
+                   .times(1)
+                   .in_sequence(sequence)
+                   .returning(move |_| Ok(DataPacket { bytes: vec![value] }));
+    }
+
+    fn expect_command(mock_camera: &mut MockCameraInterface, sequence: &mut Sequence, command: CameraCommand, is_ok: bool) {
+        mock_camera.expect_send_command()
+                   .with(eq(command))
+                   .times(1)
+                   .in_sequence(sequence)
+                   .returning(|_| Ok(()));
+        mock_camera.expect_expect_ok_response()
+                   .times(1)
+                   .in_sequence(sequence)
+                   .returning(move || if is_ok { Ok(()) } else { Err(anyhow!("")) });
+    }
+
+    fn expect_write(mock_camera: &mut MockCameraInterface, sequence: &mut Sequence, value: u8) {
+        expect_command(mock_camera, sequence, CameraCommand::WriteToMemory { address: 0x1234, values: vec![value] }, true);
+        expect_read(mock_camera, sequence, value);
+    }
+
+    #[test]
+    fn stops_should_be_parsed() {
+        assert_eq!(1.0, parse_stops("+1").unwrap());
+        assert_eq!(-0.5, parse_stops("-0.5").unwrap());
+        assert_eq!(-2.0 / 3.0, parse_stops("-2/3").unwrap());
+        assert!(parse_stops("1/0").is_err());
+        assert!(parse_stops("one").is_err());
+    }
+
+    #[test]
+    fn bracket_should_restore_the_original_compensation() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        expect_read(&mut mock_camera, &mut sequence, 0x01);
+        expect_write(&mut mock_camera, &mut sequence, 0xFD);
+        expect_command(&mut mock_camera, &mut sequence, CameraCommand::Shoot, true);
+        expect_write(&mut mock_camera, &mut sequence, 0x03);
+        expect_command(&mut mock_camera, &mut sequence, CameraCommand::Shoot, true);
+        expect_write(&mut mock_camera, &mut sequence, 0x01);
+
+        let mut released_stops: Vec<f64> = Vec::new();
+        shoot_bracket(&mut mock_camera, &SETTING, &[-1.0, 1.0], |stops| released_stops.push(stops)).unwrap();
+        assert_eq!(vec![-1.0, 1.0], released_stops);
+    }
+
+    #[test]
+    fn failed_release_should_stop_and_restore_the_original_compensation() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        expect_read(&mut mock_camera, &mut sequence, 0x00);
+        expect_write(&mut mock_camera, &mut sequence, 0xFD);
+        expect_command(&mut mock_camera, &mut sequence, CameraCommand::Shoot, false);
+        expect_write(&mut mock_camera, &mut sequence, 0x00);
+
+        assert!(shoot_bracket(&mut mock_camera, &SETTING, &[-1.0, 0.0], |_| ()).is_err());
+    }
+
+    #[test]
+    fn write_that_doesnt_read_back_should_be_error() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        expect_read(&mut mock_camera, &mut sequence, 0x00);
+        expect_command(&mut mock_camera, &mut sequence, CameraCommand::WriteToMemory { address: 0x1234, values: vec![0x03] }, true);
+        expect_read(&mut mock_camera, &mut sequence, 0x00);
+        expect_write(&mut mock_camera, &mut sequence, 0x00);
+
+        assert!(shoot_bracket(&mut mock_camera, &SETTING, &[1.0], |_| ()).is_err());
+    }
+
+    #[test]
+    fn invalid_stops_should_not_write_anything() {
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_send_command().times(0);
+
+        assert!(shoot_bracket(&mut mock_camera, &SETTING, &[0.0, 0.25], |_| ()).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::camera_interface::CameraInterface;
+use crate::camera_interface::messaging::{CameraCommand, DataPacket};
+use crate::camera_interface::models::CameraInfo;
+use crate::camera_operations;
+
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Ranges that are at most this many bytes apart are read with one command by
+/// [CachingCamera::prefetch]. At 1200 BAUD, sending another command takes about as long as
+/// reading this many more bytes.
+pub const MAX_MERGE_GAP: usize = 16;
+
+/// Sorts the ranges and merges the ones that overlap or are at most `max_gap` bytes apart.
+pub fn coalesce_ranges(mut ranges: Vec<Range<usize>>, max_gap: usize) -> Vec<Range<usize>> {
+    ranges.retain(|range| !range.is_empty());
+    ranges.sort_by_key(|range| range.start);
+    let mut coalesced: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(last) if range.start <= last.end + max_gap => last.end = last.end.max(range.end),
+            _ => coalesced.push(range),
+        }
+    }
+    return coalesced;
+}
+
+/// A [CameraInterface] that keeps the memory values that were read, and answers repeated reads
+/// without sending commands. Meant to be used for a few related reads, like reading a status.
+///
+/// Written bytes are dropped from the cache. Focus and shoot commands, and new sessions, clear
+/// the whole cache, as they change the state of the camera.
+pub struct CachingCamera<'a, T: CameraInterface> {
+    camera: &'a mut T,
+    cache: HashMap<(u8, usize), u8>,
+    /// Read that was sent, and is answered by [CameraInterface::expect_data_packet].
+    pending_read: Option<(u8, u16, u8)>,
+}
+
+impl<'a, T: CameraInterface> CachingCamera<'a, T> {
+    pub fn new(camera: &'a mut T) -> CachingCamera<'a, T> {
+        return CachingCamera { camera, cache: HashMap::new(), pending_read: None };
+    }
+
+    /// Clears the cache, for example when the camera could have changed the memory.
+    pub fn invalidate(&mut self) {
+        self.cache.clear();
+    }
+
+    fn is_cached(&self, memory_space: u8, range: &Range<usize>) -> bool {
+        return range.clone().all(|address| self.cache.contains_key(&(memory_space, address)));
+    }
+
+    fn fetch(&mut self, memory_space: u8, range: Range<usize>) -> Result<()> {
+        let values = camera_operations::read_memory_range(self.camera, memory_space, range.start as u16, range.len())?;
+        for (address, value) in range.zip(values) {
+            self.cache.insert((memory_space, address), value);
+        }
+        return Ok(());
+    }
+
+    /// Reads the ranges that are going to be needed, merging the nearby ones into one command.
+    pub fn prefetch(&mut self, memory_space: u8, ranges: &[Range<usize>]) -> Result<()> {
+        for range in coalesce_ranges(ranges.to_vec(), MAX_MERGE_GAP) {
+            if range.end > 0x10000 {
+                return Err(anyhow!("Memory range {:04X}-{:04X} is out of the address space", range.start, range.end - 1));
+            }
+            if !self.is_cached(memory_space, &range) {
+                self.fetch(memory_space, range)?;
+            }
+        }
+        return Ok(());
+    }
+
+    fn read(&mut self, memory_space: u8, address: u16, length: u8) -> Result<Vec<u8>> {
+        let range = (address as usize)..(address as usize) + (length as usize);
+        if range.end > 0x10000 {
+            return Err(anyhow!("Memory range {:04X}+{:X} is out of the address space", address, length));
+        }
+        let missing: Vec<usize> = range.clone()
+                .filter(|address| !self.cache.contains_key(&(memory_space, *address)))
+                .collect();
+        if let (Some(first), Some(last)) = (missing.first(), missing.last()) {
+            self.fetch(memory_space, *first..*last + 1)?;
+        }
+        return Ok(range.map(|address| self.cache[&(memory_space, address)]).collect());
+    }
+}
+
+impl<T: CameraInterface> CameraInterface for CachingCamera<'_, T> {
+    fn send_command(&mut self, command: &CameraCommand) -> Result<()> {
+        self.pending_read = None;
+        match command {
+            CameraCommand::ReadMemory { memory_space, address, length } => {
+                self.pending_read = Some((*memory_space, *address, *length));
+                return Ok(());
+            },
+            CameraCommand::WriteToMemory { address, values } => {
+                let range = (*address as usize)..(*address as usize) + values.len();
+                self.cache.retain(|(memory_space, address), _| *memory_space != 0 || !range.contains(address));
+            },
+            CameraCommand::Focus | CameraCommand::Shoot => self.invalidate(),
+            _ => (),
+        }
+        return self.camera.send_command(command);
+    }
+
+    fn expect_ok_response(&mut self) -> Result<()> {
+        return self.camera.expect_ok_response();
+    }
+
+    fn start_new_session(&mut self) -> Result<CameraInfo> {
+        self.invalidate();
+        return self.camera.start_new_session();
+    }
+
+    fn upgrade_to_fast_session(&mut self) -> Result<()> {
+        return self.camera.upgrade_to_fast_session();
+    }
+
+    fn end_fast_session(&mut self) -> Result<()> {
+        return self.camera.end_fast_session();
+    }
+
+    fn expect_data_packet(&mut self, payload_length: u8) -> Result<DataPacket> {
+        let Some((memory_space, address, length)) = self.pending_read.take() else {
+            return self.camera.expect_data_packet(payload_length);
+        };
+        if length != payload_length {
+            return Err(anyhow!("Expected {} bytes for the read, not {}", length, payload_length));
+        }
+        return Ok(DataPacket { bytes: self.read(memory_space, address, length)? });
+    }
+
+    fn supports_fast_session(&self) -> bool {
+        return self.camera.supports_fast_session();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_interface::fake::FakeCamera;
+
+    #[test]
+    fn nearby_ranges_should_be_coalesced() {
+        let ranges = vec![0xFD42..0xFD48, 0xFD00..0xFD04, 0xFD40..0xFD41, 0xFD44..0xFD46];
+        assert_eq!(vec![0xFD00..0xFD04, 0xFD40..0xFD48], coalesce_ranges(ranges, MAX_MERGE_GAP));
+        assert_eq!(vec![0x10..0x30], coalesce_ranges(vec![0x20..0x30, 0x10..0x11], MAX_MERGE_GAP));
+        assert_eq!(vec![0x10..0x11, 0x22..0x30], coalesce_ranges(vec![0x22..0x30, 0x10..0x11], MAX_MERGE_GAP));
+    }
+
+    #[test]
+    fn without_a_gap_only_adjacent_and_overlapping_ranges_should_be_coalesced() {
+        let ranges = vec![0xFD42..0xFD48, 0x1000..0x1001, 0xFD40..0xFD42, 0xFD46..0xFD50, 0xFD60..0xFD61];
+        assert_eq!(vec![0x1000..0x1001, 0xFD40..0xFD50, 0xFD60..0xFD61], coalesce_ranges(ranges, 0));
+    }
+
+    #[test]
+    fn prefetched_reads_should_not_send_commands() {
+        let mut fake_camera = FakeCamera::with_address_pattern();
+        let mut camera = CachingCamera::new(&mut fake_camera);
+
+        camera.prefetch(0, &[0xFD00..0xFD04, 0xFD40..0xFD41, 0xFD42..0xFD48]).unwrap();
+        assert_eq!(vec![0x40], camera_operations::read_memory(&mut camera, 0, 0xFD40, 1).unwrap());
+        assert_eq!(vec![0x42, 0x43], camera_operations::read_memory(&mut camera, 0, 0xFD42, 2).unwrap());
+        assert_eq!(vec![0x02, 0x03], camera_operations::read_memory(&mut camera, 0, 0xFD02, 2).unwrap());
+        assert_eq!(vec![(0xFD00, 4), (0xFD40, 8)], fake_camera.get_reads());
+    }
+
+    #[test]
+    fn repeated_reads_should_be_served_from_the_cache() {
+        let mut fake_camera = FakeCamera::with_address_pattern();
+        let mut camera = CachingCamera::new(&mut fake_camera);
+
+        camera_operations::read_memory(&mut camera, 0, 0x1000, 2).unwrap();
+        camera_operations::read_memory(&mut camera, 0, 0x1000, 2).unwrap();
+        camera_operations::read_memory(&mut camera, 1, 0x1000, 2).unwrap();
+        assert_eq!(vec![(0x1000, 2), (0x1000, 2)], fake_camera.get_reads());
+    }
+
+    #[test]
+    fn written_bytes_should_be_read_again() {
+        let mut fake_camera = FakeCamera::with_address_pattern();
+        let mut camera = CachingCamera::new(&mut fake_camera);
+
+        camera_operations::read_memory(&mut camera, 0, 0x1000, 4).unwrap();
+        camera_operations::write_memory(&mut camera, 0x1001, vec![0xAA]).unwrap();
+        camera_operations::read_memory(&mut camera, 0, 0x1000, 4).unwrap();
+        assert_eq!(vec![(0x1000, 4), (0x1001, 1)], fake_camera.get_reads());
+    }
+
+    #[test]
+    fn shooting_should_clear_the_cache() {
+        let mut fake_camera = FakeCamera::with_address_pattern();
+        let mut camera = CachingCamera::new(&mut fake_camera);
+
+        camera_operations::read_memory(&mut camera, 0, 0x1000, 1).unwrap();
+        camera_operations::release_shutter(&mut camera).unwrap();
+        camera_operations::read_memory(&mut camera, 0, 0x1000, 1).unwrap();
+        assert_eq!(vec![(0x1000, 1), (0x1000, 1)], fake_camera.get_reads());
+    }
+}
@@ -1,15 +1,23 @@
+pub mod fake;
 pub mod messaging;
+pub mod models;
+pub mod network;
 
 #[cfg(test)]
 use mockall::{automock, predicate::*, Sequence};
 
 use anyhow::{Context, Result, anyhow};
 use messaging::CameraCommand;
+use models::{CameraInfo, CameraProfile};
+use network::{NetworkProtocol, TcpConnection};
 use log::{warn, debug};
+use std::io::{Read, Write};
 use std::thread;
 use std::time::Duration;
 
 const DEFAULT_BAUD_RATE: u32 = 1200;
+/// Default read and write timeout of the connections.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(2000);
 
 #[cfg_attr(test, automock)]
 /// A trait for needed basic serial connection methods.
@@ -23,30 +31,88 @@ pub trait SerialInterface {
     fn clear_input(&mut self) -> Result<Vec<u8>>;
     /// Sets the BAUD rate of the serial interface.
     fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()>;
+    /// Whether [SerialInterface::set_baud_rate] can work, for example a raw TCP connection to a
+    /// serial server can't change the BAUD rate.
+    fn can_change_baud_rate(&self) -> bool;
+}
+
+impl<T: SerialInterface + ?Sized> SerialInterface for Box<T> {
+    fn read(&mut self, length: usize) -> Result<Vec<u8>> {
+        return (**self).read(length);
+    }
+
+    fn write(&mut self, data: &Vec<u8>) -> Result<()> {
+        return (**self).write(data);
+    }
+
+    fn clear_input(&mut self) -> Result<Vec<u8>> {
+        return (**self).clear_input();
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        return (**self).set_baud_rate(baud_rate);
+    }
+
+    fn can_change_baud_rate(&self) -> bool {
+        return (**self).can_change_baud_rate();
+    }
+}
+
+/// Opens a connection to the camera.
+///
+/// The device can be a native serial device, or a serial server such as ser2net given as
+/// "tcp://host:port" for a raw TCP connection, or "rfc2217://host:port" for a telnet connection
+/// that can also change the BAUD rate remotely.
+pub fn open_connection(serial_device: &String, timeout: Duration) -> Result<Box<dyn SerialInterface + Send>> {
+    if let Some(address) = serial_device.strip_prefix("tcp://") {
+        return Ok(Box::new(TcpConnection::new(address, NetworkProtocol::Raw, timeout, DEFAULT_BAUD_RATE)?));
+    }
+    if let Some(address) = serial_device.strip_prefix("rfc2217://") {
+        return Ok(Box::new(TcpConnection::new(address, NetworkProtocol::Rfc2217, timeout, DEFAULT_BAUD_RATE)?));
+    }
+    return Ok(Box::new(SerialConnection::with_timeout(serial_device, timeout)?));
 }
 
 /// An implementation for the [SerialInterface] trait.
 ///
 /// The native serial port object that implements the [serialport::SerialPort] trait can be
-/// determined during build time.
-pub struct SerialConnection<T: serialport::SerialPort> {
-    serial: T
+/// determined during build time, or a boxed [serialport::SerialPort] can be given during runtime.
+pub struct SerialConnection<T: serialport::SerialPort + ?Sized> {
+    serial: Box<T>
+}
+
+impl<T: serialport::SerialPort> SerialConnection<T> {
+    /// Uses the given serial port, which should already be configured for the camera.
+    pub fn from_port(serial_port: T) -> SerialConnection<T> {
+        return SerialConnection { serial: Box::new(serial_port) };
+    }
+}
+
+impl SerialConnection<dyn serialport::SerialPort> {
+    /// Uses the given boxed serial port, for example one that was opened with
+    /// [serialport::SerialPortBuilder::open] using custom settings.
+    pub fn from_boxed_port(serial_port: Box<dyn serialport::SerialPort>) -> SerialConnection<dyn serialport::SerialPort> {
+        return SerialConnection { serial: serial_port };
+    }
 }
 
 impl SerialConnection<serialport::TTYPort> {
     pub fn new(serial_device: &String) -> Result<SerialConnection<serialport::TTYPort>> {
-        let default_serial_timeout = 2000;
+        return SerialConnection::with_timeout(serial_device, DEFAULT_TIMEOUT);
+    }
 
+    /// Same as [SerialConnection::new], but with the given read and write timeout.
+    pub fn with_timeout(serial_device: &String, timeout: Duration) -> Result<SerialConnection<serialport::TTYPort>> {
         let serial_port = serialport::new(serial_device, DEFAULT_BAUD_RATE)
-                .timeout(Duration::from_millis(default_serial_timeout))
+                .timeout(timeout)
                 .open_native()
                 .with_context(|| format!("Could not open the serial device \"{}\"", &serial_device))?;
 
-        return Ok(SerialConnection { serial: serial_port });
+        return Ok(SerialConnection::from_port(serial_port));
     }
 }
 
-impl<T: serialport::SerialPort> SerialInterface for SerialConnection<T> {
+impl<T: serialport::SerialPort + ?Sized> SerialInterface for SerialConnection<T> {
     fn read(&mut self, length: usize) -> Result<Vec<u8>> {
         let mut read_buffer: Vec<u8> = vec![0; length];
         self.serial.read_exact(&mut read_buffer)
@@ -60,7 +126,7 @@ impl<T: serialport::SerialPort> SerialInterface for SerialConnection<T> {
             warn!("Received no bytes to write");
         }
         debug!("Sending bytes: {:02X?}", &data);
-        self.serial.write(&data.as_slice())
+        self.serial.write(data.as_slice())
                 .with_context(|| format!("Error writing given bytes: {:02X?}", data))?;
         return Ok(());
     }
@@ -82,6 +148,74 @@ impl<T: serialport::SerialPort> SerialInterface for SerialConnection<T> {
         self.serial.set_baud_rate(baud_rate)?;
         return Ok(());
     }
+
+    fn can_change_baud_rate(&self) -> bool {
+        return true;
+    }
+}
+
+/// Hook that is called to change the BAUD rate of the stream of a [StreamConnection].
+pub type BaudRateHook<S> = Box<dyn FnMut(&mut S, u32) -> Result<()> + Send>;
+
+/// An implementation for the [SerialInterface] trait over anything that implements [Read] and
+/// [Write], such as sockets and test doubles.
+///
+/// Since a generic stream can't tell how many bytes are available without blocking,
+/// [SerialInterface::clear_input] doesn't read anything from the stream.
+pub struct StreamConnection<S: Read + Write> {
+    stream: S,
+    set_baud_rate_hook: BaudRateHook<S>,
+}
+
+impl<S: Read + Write> StreamConnection<S> {
+    /// Uses the given stream. The given hook is called with the stream when the BAUD rate needs
+    /// to be changed.
+    pub fn new(stream: S, set_baud_rate_hook: BaudRateHook<S>) -> StreamConnection<S> {
+        return StreamConnection { stream, set_baud_rate_hook };
+    }
+
+    /// Uses the given stream, ignoring the BAUD rate changes. This is for streams that don't have
+    /// a BAUD rate, like pseudo terminals and test doubles.
+    pub fn with_fixed_baud_rate(stream: S) -> StreamConnection<S> {
+        return StreamConnection::new(stream, Box::new(|_, baud_rate| {
+            debug!("Ignoring BAUD rate change to {}", baud_rate);
+            return Ok(());
+        }));
+    }
+}
+
+impl<S: Read + Write> SerialInterface for StreamConnection<S> {
+    fn read(&mut self, length: usize) -> Result<Vec<u8>> {
+        let mut read_buffer: Vec<u8> = vec![0; length];
+        self.stream.read_exact(&mut read_buffer)
+                .with_context(|| format!("Error reading {} bytes.", length))?;
+        debug!("Received bytes: {:02X?}", &read_buffer);
+        return Ok(read_buffer);
+    }
+
+    fn write(&mut self, data: &Vec<u8>) -> Result<()> {
+        if data.is_empty() {
+            warn!("Received no bytes to write");
+        }
+        debug!("Sending bytes: {:02X?}", &data);
+        self.stream.write_all(data)
+                .with_context(|| format!("Error writing given bytes: {:02X?}", data))?;
+        self.stream.flush()?;
+        return Ok(());
+    }
+
+    fn clear_input(&mut self) -> Result<Vec<u8>> {
+        return Ok(Vec::new());
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        debug!("Setting BAUD rate to {}", baud_rate);
+        return (self.set_baud_rate_hook)(&mut self.stream, baud_rate);
+    }
+
+    fn can_change_baud_rate(&self) -> bool {
+        return true;
+    }
 }
 
 #[cfg_attr(test, automock)]
@@ -98,9 +232,11 @@ pub trait CameraInterface {
     fn expect_ok_response(&mut self) -> Result<()>;
     /// Starts a new 1200 BAUD session
     ///
-    /// A session is started by sending a wakeup command and then doing unit inquiry.
-    fn start_new_session(&mut self) -> Result<()>;
-    /// Upgrades the BAUD rate to 9600.
+    /// A session is started by sending a wakeup command and then doing unit inquiry. Fails if
+    /// there is no profile for the camera model given in the unit inquiry response.
+    fn start_new_session(&mut self) -> Result<CameraInfo>;
+    /// Upgrades the BAUD rate to the fast BAUD rate of the camera model, which is 9600 for
+    /// F90x.
     ///
     /// An existing 1200 BAUD rate session should have already been started.
     fn upgrade_to_fast_session(&mut self) -> Result<()>;
@@ -111,21 +247,50 @@ pub trait CameraInterface {
     fn end_fast_session(&mut self) -> Result<()>;
     /// Expect a data packet with the given payload length.
     fn expect_data_packet(&mut self, payload_length: u8) -> Result<messaging::DataPacket>;
+    /// Whether the connection can switch to the fast BAUD rate. If not,
+    /// [CameraInterface::upgrade_to_fast_session] fails without sending anything to the camera.
+    fn supports_fast_session(&self) -> bool;
 }
 
 /// An implementation of the [CameraInterface] trait.
 pub struct SerialCameraConnection<T: SerialInterface> {
-    serial: T
+    serial: T,
+    /// Profile of the connected camera, known after a session is started.
+    profile: Option<&'static CameraProfile>,
+    /// Whether the serial connection was switched to the fast BAUD rate.
+    is_fast_session: bool,
 }
 
 impl<T: SerialInterface> SerialCameraConnection<T> {
     pub fn new(serial: T) -> SerialCameraConnection<T> {
-        return SerialCameraConnection { serial };
+        return SerialCameraConnection { serial, profile: None, is_fast_session: false };
+    }
+
+    /// Reads the unit inquiry response until the "end of text" byte, and the "ack" byte after
+    /// it. The length of the response depends on the camera model.
+    fn read_unit_inquiry_response(&mut self) -> Result<Vec<u8>> {
+        const MAX_RESPONSE_LENGTH: usize = 32;
+        const ETX: u8 = 0x03;
+
+        let mut response: Vec<u8> = Vec::new();
+        while response.last() != Some(&ETX) {
+            if response.len() >= MAX_RESPONSE_LENGTH {
+                return Err(anyhow!("Unit inquiry response is too long: {:02X?}", response));
+            }
+            response.append(&mut self.serial.read(1)?);
+        }
+        response.append(&mut self.serial.read(1)?);
+        return Ok(response);
     }
 }
 
 impl<T: SerialInterface> CameraInterface for SerialCameraConnection<T> {
     fn send_command(&mut self, command: &CameraCommand) -> Result<()> {
+        if let Some(profile) = self.profile {
+            if !profile.supports(command.get_kind()) {
+                return Err(anyhow!("{:?} is not supported by {}", command.get_kind(), profile.model_name));
+            }
+        }
         debug!("Will send camera command: {:?}", command);
         self.serial.write(&command.get_bytes())
     }
@@ -138,26 +303,42 @@ impl<T: SerialInterface> CameraInterface for SerialCameraConnection<T> {
         return Ok(());
     }
 
-    fn start_new_session(&mut self) -> Result<()> {
+    fn start_new_session(&mut self) -> Result<CameraInfo> {
+        if self.is_fast_session {
+            // The camera goes back to the default BAUD rate when it goes to sleep.
+            debug!("Switching back to the default BAUD rate for the new session");
+            self.serial.set_baud_rate(DEFAULT_BAUD_RATE)?;
+            self.is_fast_session = false;
+        }
+        self.profile = None;
         self.send_command(&CameraCommand::Wakeup)?;
         thread::sleep(Duration::from_millis(200));
         // If the camera was already awake, we might get some bytes. We don't really care about them.
         // If the camera was asleep, we won't get a response.
         self.serial.clear_input()?;
         self.send_command(&CameraCommand::UnitInquiry)?;
-        let response = self.serial.read(messaging::EXPECTED_UNIT_INQUIRY_RESPONSE.len())?;
-        if response != messaging::EXPECTED_UNIT_INQUIRY_RESPONSE.to_vec() {
-            return Err(anyhow!("Unexpected unit inquiry response: {:02X?}", response));
-        }
-        return Ok(());
+        let response = self.read_unit_inquiry_response()?;
+        let camera_info = CameraInfo::parse(&response)?;
+        debug!("Unit inquiry response: {:?}", camera_info);
+        self.profile = Some(camera_info.get_profile()?);
+        return Ok(camera_info);
     }
 
     fn upgrade_to_fast_session(&mut self) -> Result<()> {
+        let profile = self.profile.ok_or(anyhow!("A session should be started before upgrading it"))?;
+        let fast_baud_rate = profile.fast_baud_rate
+                .ok_or(anyhow!("{} does not support a faster BAUD rate", profile.model_name))?;
+        // Otherwise the camera would switch to the fast BAUD rate, but the connection would not.
+        if !self.serial.can_change_baud_rate() {
+            return Err(anyhow!("The connection can't change its BAUD rate, use rfc2217:// for a serial server"));
+        }
+
         self.send_command(&CameraCommand::IncreaseBaudRate)?;
         self.expect_ok_response()?;
 
         thread::sleep(Duration::from_millis(200));
-        self.serial.set_baud_rate(9600)?;
+        self.serial.set_baud_rate(fast_baud_rate)?;
+        self.is_fast_session = true;
         return Ok(());
     }
 
@@ -172,7 +353,9 @@ impl<T: SerialInterface> CameraInterface for SerialCameraConnection<T> {
         }
 
         thread::sleep(Duration::from_millis(200));
-        self.serial.set_baud_rate(DEFAULT_BAUD_RATE)?;
+        let default_baud_rate = self.profile.map_or(DEFAULT_BAUD_RATE, |profile| profile.default_baud_rate);
+        self.serial.set_baud_rate(default_baud_rate)?;
+        self.is_fast_session = false;
         return Ok(());
     }
 
@@ -184,12 +367,76 @@ impl<T: SerialInterface> CameraInterface for SerialCameraConnection<T> {
         return messaging::DataPacket::deserialize(&response);
     }
 
+    fn supports_fast_session(&self) -> bool {
+        return self.serial.can_change_baud_rate();
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// A test double stream that returns the given input, and collects the output.
+    struct FakeStream {
+        input: std::io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for FakeStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            return self.input.read(buf);
+        }
+    }
+
+    impl Write for FakeStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            return self.output.write(buf);
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    #[test]
+    fn stream_connection_should_start_a_session_over_the_stream() {
+        let stream = FakeStream { input: std::io::Cursor::new(messaging::EXPECTED_UNIT_INQUIRY_RESPONSE.to_vec()), output: Vec::new() };
+        let mut camera_interface = SerialCameraConnection::new(StreamConnection::with_fixed_baud_rate(stream));
+        assert_eq!("F90X/N90S", camera_interface.start_new_session().unwrap().model_name);
+
+        let mut expected = CameraCommand::Wakeup.get_bytes();
+        expected.extend(CameraCommand::UnitInquiry.get_bytes());
+        assert_eq!(expected, camera_interface.serial.stream.output);
+    }
+
+    #[test]
+    fn stream_connection_should_call_the_baud_rate_hook() {
+        let stream = FakeStream { input: std::io::Cursor::new(Vec::new()), output: Vec::new() };
+        let mut connection = StreamConnection::new(stream, Box::new(|stream: &mut FakeStream, baud_rate| {
+            stream.output.extend(baud_rate.to_be_bytes());
+            return Ok(());
+        }));
+        assert!(connection.set_baud_rate(9600).is_ok());
+        assert_eq!(vec![0x00, 0x00, 0x25, 0x80], connection.stream.output);
+    }
+
+    #[test]
+    fn stream_connection_should_fail_if_stream_ends() {
+        let stream = FakeStream { input: std::io::Cursor::new(vec![0x06]), output: Vec::new() };
+        let mut connection = StreamConnection::with_fixed_baud_rate(stream);
+        assert!(connection.read(2).is_err());
+    }
+
+    #[test]
+    fn serial_connection_should_use_a_given_boxed_port() {
+        let (master, slave) = serialport::TTYPort::pair().unwrap();
+        let mut camera_side = SerialConnection::from_port(slave);
+        let mut connection = SerialConnection::from_boxed_port(Box::new(master));
+
+        connection.write(&vec![0x01, 0x02]).unwrap();
+        assert_eq!(vec![0x01, 0x02], camera_side.read(2).unwrap());
+    }
+
     #[test]
     fn send_command_should_send_command_bytes_via_serial() {
         let command = CameraCommand::UnitInquiry;
@@ -200,7 +447,7 @@ mod tests {
                    .times(1)
                    .returning(|_| Ok(()));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.send_command(&command).is_ok());
     }
 
@@ -214,7 +461,7 @@ mod tests {
                    .times(1)
                    .returning(|_| Err(anyhow!("")));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.send_command(&command).is_err());
     }
 
@@ -226,7 +473,7 @@ mod tests {
                    .times(1)
                    .returning(|_| Ok(messaging::OK_RESPONSE.to_vec()));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.expect_ok_response().is_ok());
     }
 
@@ -238,7 +485,7 @@ mod tests {
                    .times(1)
                    .returning(|_| Ok(vec![0x10u8, 0x20u8]));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.expect_ok_response().is_err());
     }
 
@@ -250,7 +497,7 @@ mod tests {
                    .times(1)
                    .returning(|_| Err(anyhow!("")));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.expect_ok_response().is_err());
     }
 
@@ -274,14 +521,45 @@ mod tests {
                    .times(1)
                    .in_sequence(&mut sequence)
                    .returning(|_| Ok(()));
+        let mut response = messaging::EXPECTED_UNIT_INQUIRY_RESPONSE.to_vec().into_iter();
         mock_serial.expect_read()
-                   .with(eq(messaging::EXPECTED_UNIT_INQUIRY_RESPONSE.len()))
+                   .with(eq(1))
+                   .times(messaging::EXPECTED_UNIT_INQUIRY_RESPONSE.len())
+                   .in_sequence(&mut sequence)
+                   .returning(move |_| Ok(vec![response.next().unwrap()]));
+
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
+        let camera_info = camera_interface.start_new_session().unwrap();
+        assert_eq!("F90X/N90S", camera_info.model_name);
+        assert_eq!(Some(&models::F90X_PROFILE), camera_interface.profile);
+    }
+
+    #[test]
+    fn start_new_session_should_switch_back_to_default_baud_rate_after_a_fast_session() {
+        let mut sequence = Sequence::new();
+        let mut mock_serial = MockSerialInterface::new();
+        mock_serial.expect_set_baud_rate()
+                   .with(eq(1200))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|_| Ok(()));
+        mock_serial.expect_write()
+                   .with(eq(CameraCommand::Wakeup.get_bytes()))
                    .times(1)
                    .in_sequence(&mut sequence)
-                   .returning(|_| Ok(messaging::EXPECTED_UNIT_INQUIRY_RESPONSE.to_vec()));
+                   .returning(|_| Ok(()));
+        mock_serial.expect_clear_input()
+                   .returning(|| Ok(Vec::new()));
+        mock_serial.expect_write()
+                   .returning(|_| Ok(()));
+        let mut response = messaging::EXPECTED_UNIT_INQUIRY_RESPONSE.to_vec().into_iter();
+        mock_serial.expect_read()
+                   .with(eq(1))
+                   .returning(move |_| Ok(vec![response.next().unwrap()]));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection { serial: mock_serial, profile: Some(&models::F90X_PROFILE), is_fast_session: true };
         assert!(camera_interface.start_new_session().is_ok());
+        assert!(!camera_interface.is_fast_session);
     }
 
     #[test]
@@ -296,7 +574,7 @@ mod tests {
                    .with(ne(CameraCommand::Wakeup.get_bytes()))
                    .returning(|_| Ok(()));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.start_new_session().is_err());
     }
 
@@ -308,7 +586,7 @@ mod tests {
         mock_serial.expect_clear_input()
                    .returning(|| Err(anyhow!("")));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.start_new_session().is_err());
     }
 
@@ -324,7 +602,7 @@ mod tests {
                    .with(eq(CameraCommand::UnitInquiry.get_bytes()))
                    .returning(|_| Err(anyhow!("")));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.start_new_session().is_err());
     }
 
@@ -336,10 +614,10 @@ mod tests {
         mock_serial.expect_clear_input()
                    .returning(|| Ok(vec![0u8]));
         mock_serial.expect_read()
-                   .with(eq(messaging::EXPECTED_UNIT_INQUIRY_RESPONSE.len()))
+                   .with(eq(1))
                    .returning(|_| Err(anyhow!("")));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.start_new_session().is_err());
     }
 
@@ -351,13 +629,49 @@ mod tests {
         mock_serial.expect_clear_input()
                    .returning(|| Ok(vec![0u8]));
         mock_serial.expect_read()
-                   .with(eq(messaging::EXPECTED_UNIT_INQUIRY_RESPONSE.len()))
-                   .returning(|_| Ok(vec![1u8; messaging::EXPECTED_UNIT_INQUIRY_RESPONSE.len()]));
+                   .with(eq(1))
+                   .returning(|_| Ok(vec![1u8]));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.start_new_session().is_err());
     }
 
+    #[test]
+    fn start_new_session_should_fail_if_camera_model_is_unsupported() {
+        let mut mock_serial = MockSerialInterface::new();
+        mock_serial.expect_write()
+                   .returning(|_| Ok(()));
+        mock_serial.expect_clear_input()
+                   .returning(|| Ok(vec![0u8]));
+        let mut response = b"1020F90/N90\x00\x03\x06".to_vec().into_iter();
+        mock_serial.expect_read()
+                   .with(eq(1))
+                   .returning(move |_| Ok(vec![response.next().unwrap()]));
+
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
+        let error = camera_interface.start_new_session().unwrap_err();
+        let unsupported = error.downcast_ref::<models::UnsupportedModelError>().unwrap();
+        assert_eq!("F90/N90", unsupported.camera_info.model_name);
+        assert!(camera_interface.profile.is_none());
+    }
+
+    #[test]
+    fn send_command_should_fail_if_command_is_not_supported_by_the_camera() {
+        const PROFILE_WITHOUT_FOCUS: models::CameraProfile = models::CameraProfile {
+            supported_commands: &[messaging::CommandKind::Shoot],
+            ..models::F90X_PROFILE
+        };
+        let mut mock_serial = MockSerialInterface::new();
+        mock_serial.expect_write()
+                   .with(eq(CameraCommand::Shoot.get_bytes()))
+                   .times(1)
+                   .returning(|_| Ok(()));
+
+        let mut camera_interface = SerialCameraConnection { serial: mock_serial, profile: Some(&PROFILE_WITHOUT_FOCUS), is_fast_session: false };
+        assert!(camera_interface.send_command(&CameraCommand::Focus).is_err());
+        assert!(camera_interface.send_command(&CameraCommand::Shoot).is_ok());
+    }
+
     #[test]
     fn expect_data_packet_should_read_from_serial() {
         const EXPECTED_PAYLOAD: &'static [u8] = &[0x11, 0x22, 0x33];
@@ -367,7 +681,7 @@ mod tests {
                    .times(1)
                    .returning(|_| Ok(messaging::DataPacket { bytes: EXPECTED_PAYLOAD.to_vec() }.serialize()));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
 
         let result = camera_interface.expect_data_packet(3);
         assert!(result.is_ok());
@@ -382,7 +696,7 @@ mod tests {
                    .times(1)
                    .returning(|_| Err(anyhow!("")));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
 
         assert!(camera_interface.expect_data_packet(3).is_err());
     }
@@ -398,7 +712,7 @@ mod tests {
                    .times(1)
                    .returning(|_| Ok(INVALID_RESPONSE.to_vec()));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
 
         assert!(camera_interface.expect_data_packet(1).is_err());
     }
@@ -409,6 +723,7 @@ mod tests {
     fn upgrade_to_fast_session_should_send_correct_messages() {
         let mut sequence = Sequence::new();
         let mut mock_serial = MockSerialInterface::new();
+        mock_serial.expect_can_change_baud_rate().return_const(true);
         mock_serial.expect_write()
                    .with(eq(CameraCommand::IncreaseBaudRate.get_bytes()))
                    .times(1)
@@ -425,7 +740,7 @@ mod tests {
                    .in_sequence(&mut sequence)
                    .returning(|_| Ok(()));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection { serial: mock_serial, profile: Some(&models::F90X_PROFILE), is_fast_session: false };
         assert!(camera_interface.upgrade_to_fast_session().is_ok());
     }
 
@@ -433,6 +748,7 @@ mod tests {
     fn upgrade_to_fast_session_should_fail_if_ok_response_is_not_received() {
         let mut sequence = Sequence::new();
         let mut mock_serial = MockSerialInterface::new();
+        mock_serial.expect_can_change_baud_rate().return_const(true);
         mock_serial.expect_write()
                    .with(eq(CameraCommand::IncreaseBaudRate.get_bytes()))
                    .times(1)
@@ -444,7 +760,29 @@ mod tests {
                    .in_sequence(&mut sequence)
                    .returning(|_| Ok(vec![0x10u8, 0x20u8]));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection { serial: mock_serial, profile: Some(&models::F90X_PROFILE), is_fast_session: false };
+        assert!(camera_interface.upgrade_to_fast_session().is_err());
+    }
+
+    #[test]
+    fn upgrade_to_fast_session_should_not_touch_the_camera_if_baud_rate_cannot_change() {
+        let mut mock_serial = MockSerialInterface::new();
+        mock_serial.expect_can_change_baud_rate().return_const(false);
+        mock_serial.expect_write().times(0);
+        mock_serial.expect_set_baud_rate().times(0);
+
+        let mut camera_interface = SerialCameraConnection { serial: mock_serial, profile: Some(&models::F90X_PROFILE), is_fast_session: false };
+        assert!(!camera_interface.supports_fast_session());
+        assert!(camera_interface.upgrade_to_fast_session().is_err());
+        assert!(!camera_interface.is_fast_session);
+    }
+
+    #[test]
+    fn upgrade_to_fast_session_should_fail_without_a_session() {
+        let mut mock_serial = MockSerialInterface::new();
+        mock_serial.expect_write().times(0);
+
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.upgrade_to_fast_session().is_err());
     }
 
@@ -468,7 +806,7 @@ mod tests {
                    .in_sequence(&mut sequence)
                    .returning(|_| Ok(()));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.end_fast_session().is_ok());
     }
 
@@ -487,7 +825,7 @@ mod tests {
                    .in_sequence(&mut sequence)
                    .returning(|_| Ok(vec![0x01u8, 0x01u8]));
 
-        let mut camera_interface = SerialCameraConnection {serial: mock_serial};
+        let mut camera_interface = SerialCameraConnection::new(mock_serial);
         assert!(camera_interface.end_fast_session().is_err());
     }
 
@@ -0,0 +1,152 @@
+use super::CameraInterface;
+use super::messaging::{CameraCommand, DataPacket};
+use super::models::CameraInfo;
+
+use anyhow::{Result, anyhow};
+use std::ops::Range;
+
+/// A [CameraInterface] with the camera memory in a vector, for testing code that uses the
+/// library without a camera.
+///
+/// The reads of all memory spaces are answered from the same memory, and the writes change it.
+/// Every sent command is recorded in [FakeCamera::sent_commands].
+pub struct FakeCamera {
+    pub memory: Vec<u8>,
+    /// Returned when a session is started.
+    pub camera_info: CameraInfo,
+    /// Payload of the answer to [CameraCommand::ReadMemoHolderInfo].
+    pub memo_holder_info: Vec<u8>,
+    /// Reads starting in this range fail, like the reads that time out on a camera.
+    pub unreadable_addresses: Range<u16>,
+    pub sent_commands: Vec<CameraCommand>,
+    /// Command whose data packet hasn't been read yet.
+    pending_command: Option<CameraCommand>,
+}
+
+impl FakeCamera {
+    /// Creates a F90x with the given memory. Roll 1 with no bytes to read is in the memo holder.
+    pub fn new(memory: Vec<u8>) -> FakeCamera {
+        return FakeCamera {
+            memory,
+            camera_info: CameraInfo { unit_code: String::from("1020"), model_name: String::from("F90X/N90S") },
+            memo_holder_info: vec![0x01, 0x00, 0x00, 0x00],
+            unreadable_addresses: 0..0,
+            sent_commands: Vec::new(),
+            pending_command: None,
+        };
+    }
+
+    /// Creates a camera with a full 64 KiB memory, where each byte is the low byte of its address.
+    pub fn with_address_pattern() -> FakeCamera {
+        return FakeCamera::new((0..0x10000).map(|address| address as u8).collect());
+    }
+
+    /// Returns the address and length of each sent read command.
+    pub fn get_reads(&self) -> Vec<(u16, u8)> {
+        return self.sent_commands.iter()
+                .filter_map(|command| match command {
+                    CameraCommand::ReadMemory { address, length, .. } => Some((*address, *length)),
+                    _ => None,
+                })
+                .collect();
+    }
+
+    /// Returns the address and values of each sent write command.
+    pub fn get_writes(&self) -> Vec<(u16, Vec<u8>)> {
+        return self.sent_commands.iter()
+                .filter_map(|command| match command {
+                    CameraCommand::WriteToMemory { address, values } => Some((*address, values.clone())),
+                    _ => None,
+                })
+                .collect();
+    }
+
+    fn read(&self, address: u16, length: u8) -> Result<Vec<u8>> {
+        if self.unreadable_addresses.contains(&address) {
+            return Err(anyhow!("Timeout"));
+        }
+        let range = (address as usize)..(address as usize) + (length as usize);
+        return self.memory.get(range).map(|values| values.to_vec())
+                .ok_or(anyhow!("Memory range {:04X}+{:X} is out of the memory", address, length));
+    }
+}
+
+impl CameraInterface for FakeCamera {
+    fn send_command(&mut self, command: &CameraCommand) -> Result<()> {
+        self.pending_command = None;
+        match command {
+            CameraCommand::WriteToMemory { address, values } => {
+                let range = (*address as usize)..(*address as usize) + values.len();
+                self.memory.get_mut(range).ok_or(anyhow!("Write to {:04X} is out of the memory", address))?
+                        .copy_from_slice(values);
+            },
+            CameraCommand::ReadMemory { .. } | CameraCommand::ReadMemoHolderInfo => {
+                self.pending_command = Some(command.clone());
+            },
+            _ => (),
+        }
+        self.sent_commands.push(command.clone());
+        return Ok(());
+    }
+
+    fn expect_ok_response(&mut self) -> Result<()> {
+        return Ok(());
+    }
+
+    fn start_new_session(&mut self) -> Result<CameraInfo> {
+        self.sent_commands.push(CameraCommand::Wakeup);
+        self.sent_commands.push(CameraCommand::UnitInquiry);
+        return Ok(self.camera_info.clone());
+    }
+
+    fn upgrade_to_fast_session(&mut self) -> Result<()> {
+        self.sent_commands.push(CameraCommand::IncreaseBaudRate);
+        return Ok(());
+    }
+
+    fn end_fast_session(&mut self) -> Result<()> {
+        return Ok(());
+    }
+
+    fn expect_data_packet(&mut self, payload_length: u8) -> Result<DataPacket> {
+        let bytes = match self.pending_command.take() {
+            Some(CameraCommand::ReadMemory { address, length, .. }) if length == payload_length => {
+                self.read(address, length)?
+            },
+            Some(CameraCommand::ReadMemoHolderInfo) if self.memo_holder_info.len() == payload_length as usize => {
+                self.memo_holder_info.clone()
+            },
+            _ => return Err(anyhow!("No data packet of {} bytes was requested", payload_length)),
+        };
+        return Ok(DataPacket { bytes });
+    }
+
+    fn supports_fast_session(&self) -> bool {
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_operations;
+
+    #[test]
+    fn writes_should_change_the_memory_that_is_read() {
+        let mut camera = FakeCamera::with_address_pattern();
+        assert_eq!(vec![0x40, 0x41], camera_operations::read_memory(&mut camera, 0, 0xFD40, 2).unwrap());
+        camera_operations::write_memory(&mut camera, 0xFD40, vec![0x5F]).unwrap();
+        assert_eq!(vec![0x5F, 0x41], camera_operations::read_memory(&mut camera, 0, 0xFD40, 2).unwrap());
+        assert_eq!(vec![(0xFD40, 2), (0xFD40, 2)], camera.get_reads());
+        assert_eq!(vec![(0xFD40, vec![0x5F])], camera.get_writes());
+    }
+
+    #[test]
+    fn unreadable_addresses_should_fail() {
+        let mut camera = FakeCamera::with_address_pattern();
+        camera.unreadable_addresses = 0x1000..0x1040;
+        assert!(camera_operations::read_memory(&mut camera, 0, 0x1010, 1).is_err());
+        assert!(camera_operations::read_memory(&mut camera, 0, 0x1040, 1).is_ok());
+        assert!(camera.expect_data_packet(1).is_err());
+    }
+}
@@ -3,11 +3,12 @@ use log::error;
 
 pub const OK_RESPONSE: &'static [u8] = &[0x06, 0x00];
 // "1020F90X/N90S[null][end of text][ack]"
+#[cfg(test)]
 pub const EXPECTED_UNIT_INQUIRY_RESPONSE: &'static [u8; 16] = &[
     0x31, 0x30, 0x32, 0x30, 0x46, 0x39, 0x30, 0x58, 0x2F, 0x4E, 0x39, 0x30, 0x53, 0x00, 0x03, 0x06
 ];
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CameraCommand {
     Wakeup,
     UnitInquiry,
@@ -26,6 +27,19 @@ pub enum CameraCommand {
     },
 }
 
+/// Kind of a [CameraCommand], without its parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandKind {
+    Wakeup,
+    UnitInquiry,
+    Focus,
+    Shoot,
+    IncreaseBaudRate,
+    ReadMemoHolderInfo,
+    ReadMemory,
+    WriteToMemory,
+}
+
 impl CameraCommand {
     pub fn get_bytes(&self) -> Vec<u8> {
         match self {
@@ -39,11 +53,24 @@ impl CameraCommand {
                 CameraCommand::build_read_memory_command(*memory_space, *address, *length)
             },
             CameraCommand::WriteToMemory { address, values } => {
-                CameraCommand::build_write_to_memory_command(*address, &values)
+                CameraCommand::build_write_to_memory_command(*address, values)
             },
         }
     }
 
+    pub fn get_kind(&self) -> CommandKind {
+        match self {
+            CameraCommand::Wakeup => CommandKind::Wakeup,
+            CameraCommand::UnitInquiry => CommandKind::UnitInquiry,
+            CameraCommand::Focus => CommandKind::Focus,
+            CameraCommand::Shoot => CommandKind::Shoot,
+            CameraCommand::IncreaseBaudRate => CommandKind::IncreaseBaudRate,
+            CameraCommand::ReadMemoHolderInfo => CommandKind::ReadMemoHolderInfo,
+            CameraCommand::ReadMemory { .. } => CommandKind::ReadMemory,
+            CameraCommand::WriteToMemory { .. } => CommandKind::WriteToMemory,
+        }
+    }
+
     fn build_read_memory_command(memory_space: u8, address: u16, length: u8) -> Vec<u8> {
         vec![0x01, 0x20, 0x80,
              memory_space,
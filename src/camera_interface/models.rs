@@ -0,0 +1,293 @@
+use anyhow::{Result, anyhow};
+use super::messaging::CommandKind;
+use std::fmt;
+use crate::settings_types::{AfMode, Aperture, ExposureMode, FilmAdvanceMode, FlashSyncMode, MeteringMode, ShutterSpeed};
+
+const ETX: u8 = 0x03;
+const ACK: u8 = 0x06;
+
+/// Camera bodies that have a profile in [PROFILES].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraModel {
+    F90xN90s,
+}
+
+/// Parsed unit inquiry response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraInfo {
+    /// Code that comes before the model name, e.g. "1020".
+    pub unit_code: String,
+    /// Model name as reported by the camera, e.g. "F90X/N90S".
+    pub model_name: String,
+}
+
+/// Error of a camera that answers the unit inquiry, but has no profile in [PROFILES]. Can be
+/// found with [anyhow::Error::downcast_ref].
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedModelError {
+    pub camera_info: CameraInfo,
+}
+
+impl fmt::Display for UnsupportedModelError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(formatter, "Unsupported camera model: \"{}\"", self.camera_info.model_name);
+    }
+}
+
+impl std::error::Error for UnsupportedModelError {}
+
+/// Known memory locations of a camera model.
+#[derive(Debug, PartialEq)]
+pub struct MemoryMap {
+    /// Start and end addresses of the memo holder ring buffer, 2 little endian u16 values.
+    pub ring_buffer_addresses: u16,
+    /// Memo holder setting, 1 byte.
+    pub memo_holder_setting: u16,
+    /// Memo holder current, start and current roll start addresses, 3 little endian u16 values.
+    pub memo_holder_addresses: u16,
+}
+
+/// Exposure compensation value, stored as a signed number of steps.
+#[derive(Debug, PartialEq)]
+pub struct ExposureCompensationSetting {
+    pub address: u16,
+    /// Number of steps in one stop, e.g. 3 for 1/3 EV steps.
+    pub steps_per_stop: u8,
+}
+
+impl ExposureCompensationSetting {
+    /// Converts stops to the stored value, failing if the stops are not a multiple of the step.
+    pub fn stops_to_value(&self, stops: f64) -> Result<u8> {
+        let steps = stops * (self.steps_per_stop as f64);
+        let rounded_steps = steps.round();
+        if (steps - rounded_steps).abs() > 0.05 {
+            return Err(anyhow!("{} stops is not a multiple of 1/{} stop", stops, self.steps_per_stop));
+        }
+        if rounded_steps < (i8::MIN as f64) || rounded_steps > (i8::MAX as f64) {
+            return Err(anyhow!("{} stops is out of range", stops));
+        }
+        return Ok((rounded_steps as i8) as u8);
+    }
+
+    pub fn value_to_stops(&self, value: u8) -> f64 {
+        return (value as i8) as f64 / (self.steps_per_stop as f64);
+    }
+}
+
+/// One byte setting, with the stored value of each setting that the model supports.
+#[derive(Debug, PartialEq)]
+pub struct SettingLocation<V: 'static> {
+    pub address: u16,
+    pub values: &'static [(u8, V)],
+}
+
+/// Memory locations of the camera settings. The locations that are not known for a model are
+/// None.
+#[derive(Debug, PartialEq)]
+pub struct SettingsMap {
+    pub exposure_mode: Option<SettingLocation<ExposureMode>>,
+    pub shutter_speed: Option<SettingLocation<ShutterSpeed>>,
+    pub aperture: Option<SettingLocation<Aperture>>,
+    pub exposure_compensation: Option<ExposureCompensationSetting>,
+    pub metering_mode: Option<SettingLocation<MeteringMode>>,
+    pub af_mode: Option<SettingLocation<AfMode>>,
+    pub film_advance_mode: Option<SettingLocation<FilmAdvanceMode>>,
+    pub flash_sync_mode: Option<SettingLocation<FlashSyncMode>>,
+}
+
+impl SettingsMap {
+    /// Returns the names of the settings whose location is known, like "exposure_mode".
+    pub fn get_mapped_names(&self) -> Vec<&'static str> {
+        let settings = [
+            ("exposure_mode", self.exposure_mode.is_some()),
+            ("shutter_speed", self.shutter_speed.is_some()),
+            ("aperture", self.aperture.is_some()),
+            ("exposure_compensation", self.exposure_compensation.is_some()),
+            ("metering_mode", self.metering_mode.is_some()),
+            ("af_mode", self.af_mode.is_some()),
+            ("film_advance_mode", self.film_advance_mode.is_some()),
+            ("flash_sync_mode", self.flash_sync_mode.is_some()),
+        ];
+        return settings.iter().filter(|(_, is_mapped)| *is_mapped).map(|(name, _)| *name).collect();
+    }
+}
+
+/// A bit flag in one byte.
+#[derive(Debug, PartialEq)]
+pub struct FlagLocation {
+    pub address: u16,
+    /// The flag is set if any of these bits is set.
+    pub mask: u8,
+}
+
+/// Memory locations of the camera state, that are read for the status. The locations that are
+/// not known for a model are None.
+#[derive(Debug, PartialEq)]
+pub struct StatusMap {
+    /// Frame counter, 1 byte.
+    pub frame_counter: Option<u16>,
+    pub film_loaded: Option<FlagLocation>,
+    /// ISO of the film from the DX code, little endian u16.
+    pub dx_iso: Option<u16>,
+    pub cpu_lens_mounted: Option<FlagLocation>,
+    /// Focal length of the lens in millimeters, little endian u16.
+    pub focal_length: Option<u16>,
+    pub max_aperture: Option<SettingLocation<Aperture>>,
+    /// Light meter reading, 1 byte. Shown as the raw value, as the encoding is not known yet.
+    pub meter_reading: Option<u16>,
+}
+
+/// Everything that is known about a camera model.
+#[derive(Debug, PartialEq)]
+pub struct CameraProfile {
+    pub model: CameraModel,
+    /// Model name as it appears in the unit inquiry response.
+    pub model_name: &'static str,
+    pub supported_commands: &'static [CommandKind],
+    /// BAUD rate that a new session is started with.
+    pub default_baud_rate: u32,
+    /// BAUD rate that [CommandKind::IncreaseBaudRate] switches to, if supported.
+    pub fast_baud_rate: Option<u32>,
+    pub memory_map: MemoryMap,
+    pub settings_map: SettingsMap,
+    pub status_map: StatusMap,
+}
+
+impl CameraProfile {
+    pub fn supports(&self, command: CommandKind) -> bool {
+        return self.supported_commands.contains(&command);
+    }
+}
+
+pub const F90X_PROFILE: CameraProfile = CameraProfile {
+    model: CameraModel::F90xN90s,
+    model_name: "F90X/N90S",
+    supported_commands: &[
+        CommandKind::Wakeup,
+        CommandKind::UnitInquiry,
+        CommandKind::Focus,
+        CommandKind::Shoot,
+        CommandKind::IncreaseBaudRate,
+        CommandKind::ReadMemoHolderInfo,
+        CommandKind::ReadMemory,
+        CommandKind::WriteToMemory,
+    ],
+    default_baud_rate: 1200,
+    fast_baud_rate: Some(9600),
+    memory_map: MemoryMap {
+        ring_buffer_addresses: 0xFD00,
+        memo_holder_setting: 0xFD40,
+        memo_holder_addresses: 0xFD42,
+    },
+    // None of the setting locations of the F90x is known yet, so the camera settings can't be
+    // accessed on it. A location can be added here once it is found with the dump and watch
+    // commands.
+    settings_map: SettingsMap {
+        exposure_mode: None,
+        shutter_speed: None,
+        aperture: None,
+        exposure_compensation: None,
+        metering_mode: None,
+        af_mode: None,
+        film_advance_mode: None,
+        flash_sync_mode: None,
+    },
+    // The camera state is not mapped yet either.
+    status_map: StatusMap {
+        frame_counter: None,
+        film_loaded: None,
+        dx_iso: None,
+        cpu_lens_mounted: None,
+        focal_length: None,
+        max_aperture: None,
+        meter_reading: None,
+    },
+};
+
+/// All the camera models that this tool knows how to talk to. Other bodies that use the same
+/// 10-pin protocol can be supported by adding an entry here.
+pub const PROFILES: &[&CameraProfile] = &[&F90X_PROFILE];
+
+impl CameraInfo {
+    /// Parses the unit inquiry response.
+    ///
+    /// The response looks like "1020F90X/N90S[null][end of text][ack]", the first 4 characters
+    /// being the unit code and the rest until the null character being the model name.
+    pub fn parse(response: &[u8]) -> Result<CameraInfo> {
+        const UNIT_CODE_LENGTH: usize = 4;
+        let malformed = || anyhow!("Malformed unit inquiry response: {:02X?}", response);
+
+        let text = match response {
+            [text @ .., 0x00, ETX, ACK] => text,
+            _ => return Err(malformed()),
+        };
+        if text.len() <= UNIT_CODE_LENGTH || !text.is_ascii() {
+            return Err(malformed());
+        }
+        let text = std::str::from_utf8(text).map_err(|_| malformed())?;
+        let (unit_code, model_name) = text.split_at(UNIT_CODE_LENGTH);
+
+        return Ok(CameraInfo { unit_code: unit_code.to_string(), model_name: model_name.to_string() });
+    }
+
+    /// Finds the profile for the reported model. Fails with [UnsupportedModelError] if there is
+    /// none.
+    pub fn get_profile(&self) -> Result<&'static CameraProfile> {
+        return PROFILES.iter()
+                .find(|profile| profile.model_name == self.model_name)
+                .copied()
+                .ok_or_else(|| UnsupportedModelError { camera_info: self.clone() }.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_interface::messaging::EXPECTED_UNIT_INQUIRY_RESPONSE;
+
+    #[test]
+    fn f90x_unit_inquiry_response_should_be_parsed_correctly() {
+        let info = CameraInfo::parse(EXPECTED_UNIT_INQUIRY_RESPONSE).unwrap();
+        assert_eq!("1020", info.unit_code);
+        assert_eq!("F90X/N90S", info.model_name);
+        assert_eq!(CameraModel::F90xN90s, info.get_profile().unwrap().model);
+    }
+
+    #[test]
+    fn other_models_should_be_parsed_but_have_no_profile() {
+        let info = CameraInfo::parse(b"1020F90/N90\x00\x03\x06").unwrap();
+        assert_eq!("F90/N90", info.model_name);
+        let error = info.get_profile().unwrap_err();
+        assert_eq!(Some(&UnsupportedModelError { camera_info: info.clone() }), error.downcast_ref());
+        assert_eq!("Unsupported camera model: \"F90/N90\"", error.to_string());
+    }
+
+    #[test]
+    fn unit_inquiry_response_without_terminator_should_be_error() {
+        assert!(CameraInfo::parse(b"1020F90X/N90S\x00\x03").is_err());
+        assert!(CameraInfo::parse(b"1020F90X/N90S\x03\x06").is_err());
+    }
+
+    #[test]
+    fn unit_inquiry_response_without_model_name_should_be_error() {
+        assert!(CameraInfo::parse(b"1020\x00\x03\x06").is_err());
+        assert!(CameraInfo::parse(b"\x00\x03\x06").is_err());
+    }
+
+    #[test]
+    fn exposure_compensation_should_be_stored_as_signed_steps() {
+        let setting = ExposureCompensationSetting { address: 0x1234, steps_per_stop: 3 };
+        assert_eq!(0x03, setting.stops_to_value(1.0).unwrap());
+        assert_eq!(0xFE, setting.stops_to_value(-2.0 / 3.0).unwrap());
+        assert_eq!(0x00, setting.stops_to_value(0.0).unwrap());
+        assert!(setting.stops_to_value(0.5).is_err());
+        assert!(setting.stops_to_value(100.0).is_err());
+        assert_eq!(-1.0, setting.value_to_stops(0xFD));
+    }
+
+    #[test]
+    fn f90x_should_support_fast_session() {
+        assert_eq!(Some(9600), F90X_PROFILE.fast_baud_rate);
+        assert!(F90X_PROFILE.supports(CommandKind::IncreaseBaudRate));
+    }
+}
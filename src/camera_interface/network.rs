@@ -0,0 +1,393 @@
+use super::SerialInterface;
+
+use anyhow::{Context, Result, anyhow};
+use log::{debug, warn};
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+// Telnet and RFC 2217 constants
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const BINARY_OPTION: u8 = 0;
+const COM_PORT_OPTION: u8 = 44;
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const PURGE_DATA: u8 = 12;
+const PARITY_NONE: u8 = 1;
+const STOPSIZE_1: u8 = 1;
+const PURGE_RECEIVE_BUFFER: u8 = 1;
+
+/// Protocol that is used with the serial server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NetworkProtocol {
+    /// The bytes are forwarded as they are. The BAUD rate can't be changed remotely.
+    Raw,
+    /// Telnet with the COM port control option of RFC 2217, which allows changing the BAUD rate.
+    Rfc2217,
+}
+
+/// An implementation for the [SerialInterface] trait that talks to a serial server, such as
+/// ser2net, over TCP.
+pub struct TcpConnection {
+    stream: TcpStream,
+    protocol: NetworkProtocol,
+    /// Received bytes that were not read yet, with the telnet commands removed.
+    received: Vec<u8>,
+    telnet_decoder: TelnetDecoder,
+}
+
+impl TcpConnection {
+    /// Connects to the given "host:port" address.
+    pub fn new(address: &str, protocol: NetworkProtocol, timeout: Duration, baud_rate: u32) -> Result<TcpConnection> {
+        let stream = TcpStream::connect(address)
+                .with_context(|| format!("Could not connect to the serial server \"{}\"", address))?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+
+        let mut connection = TcpConnection {
+            stream,
+            protocol,
+            received: Vec::new(),
+            telnet_decoder: TelnetDecoder::new(),
+        };
+        if protocol == NetworkProtocol::Rfc2217 {
+            connection.negotiate_com_port_options(baud_rate)?;
+        }
+        return Ok(connection);
+    }
+
+    fn negotiate_com_port_options(&mut self, baud_rate: u32) -> Result<()> {
+        self.write_raw(&[
+            IAC, WILL, BINARY_OPTION,
+            IAC, DO, BINARY_OPTION,
+            IAC, WILL, COM_PORT_OPTION,
+        ])?;
+        self.send_com_port_command(SET_DATASIZE, &[8])?;
+        self.send_com_port_command(SET_PARITY, &[PARITY_NONE])?;
+        self.send_com_port_command(SET_STOPSIZE, &[STOPSIZE_1])?;
+        self.send_com_port_command(SET_BAUDRATE, &baud_rate.to_be_bytes())?;
+        return Ok(());
+    }
+
+    fn send_com_port_command(&mut self, command: u8, value: &[u8]) -> Result<()> {
+        let mut message = vec![IAC, SB, COM_PORT_OPTION, command];
+        message.extend(escape_iac(value));
+        message.extend([IAC, SE]);
+        return self.write_raw(&message);
+    }
+
+    fn write_raw(&mut self, data: &[u8]) -> Result<()> {
+        self.stream.write_all(data)
+                .with_context(|| format!("Error writing given bytes: {:02X?}", data))?;
+        return Ok(());
+    }
+
+    /// Reads what is available from the socket into the received bytes. Returns false if
+    /// nothing could be read.
+    fn receive(&mut self) -> Result<bool> {
+        let mut buffer = [0u8; 256];
+        let num_bytes = match self.stream.read(&mut buffer) {
+            Ok(0) => return Err(anyhow!("Connection closed by the serial server")),
+            Ok(num_bytes) => num_bytes,
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return Ok(false),
+            Err(error) => return Err(error.into()),
+        };
+        match self.protocol {
+            NetworkProtocol::Raw => self.received.extend(&buffer[..num_bytes]),
+            NetworkProtocol::Rfc2217 => {
+                let data = self.telnet_decoder.decode(&buffer[..num_bytes]);
+                self.received.extend(data);
+            },
+        }
+        return Ok(true);
+    }
+}
+
+impl SerialInterface for TcpConnection {
+    fn read(&mut self, length: usize) -> Result<Vec<u8>> {
+        while self.received.len() < length {
+            if !self.receive()? {
+                return Err(anyhow!("Error reading {} bytes. Timed out after receiving {:02X?}",
+                                   length, self.received));
+            }
+        }
+        let read_buffer: Vec<u8> = self.received.drain(..length).collect();
+        debug!("Received bytes: {:02X?}", &read_buffer);
+        return Ok(read_buffer);
+    }
+
+    fn write(&mut self, data: &Vec<u8>) -> Result<()> {
+        if data.is_empty() {
+            warn!("Received no bytes to write");
+        }
+        debug!("Sending bytes: {:02X?}", &data);
+        match self.protocol {
+            NetworkProtocol::Raw => self.write_raw(data),
+            NetworkProtocol::Rfc2217 => self.write_raw(&escape_iac(data)),
+        }
+    }
+
+    fn clear_input(&mut self) -> Result<Vec<u8>> {
+        if self.protocol == NetworkProtocol::Rfc2217 {
+            self.send_com_port_command(PURGE_DATA, &[PURGE_RECEIVE_BUFFER])?;
+        }
+        self.stream.set_nonblocking(true)?;
+        let result = (|| -> Result<()> {
+            while self.receive()? {}
+            return Ok(());
+        })();
+        self.stream.set_nonblocking(false)?;
+        result?;
+
+        let cleared: Vec<u8> = self.received.drain(..).collect();
+        if !cleared.is_empty() {
+            debug!("Cleaned the bytes from the input buffer: {:02X?}", &cleared);
+        }
+        return Ok(cleared);
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        if self.protocol != NetworkProtocol::Rfc2217 {
+            return Err(anyhow!("Changing the BAUD rate of a serial server needs an rfc2217:// connection"));
+        }
+        debug!("Setting BAUD rate to {}", baud_rate);
+        return self.send_com_port_command(SET_BAUDRATE, &baud_rate.to_be_bytes());
+    }
+
+    fn can_change_baud_rate(&self) -> bool {
+        return self.protocol == NetworkProtocol::Rfc2217;
+    }
+}
+
+/// Doubles the IAC bytes, so that they are not interpreted as telnet commands.
+fn escape_iac(data: &[u8]) -> Vec<u8> {
+    let mut escaped: Vec<u8> = Vec::with_capacity(data.len());
+    for &byte in data {
+        escaped.push(byte);
+        if byte == IAC {
+            escaped.push(IAC);
+        }
+    }
+    return escaped;
+}
+
+#[derive(Debug, PartialEq)]
+enum TelnetState {
+    Data,
+    Iac,
+    /// Option negotiation, after WILL, WONT, DO or DONT.
+    Negotiation,
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// Removes the telnet commands from the received bytes. The state is kept between calls, since
+/// a command can be split between TCP reads.
+struct TelnetDecoder {
+    state: TelnetState,
+    subnegotiation: Vec<u8>,
+}
+
+impl TelnetDecoder {
+    fn new() -> TelnetDecoder {
+        return TelnetDecoder { state: TelnetState::Data, subnegotiation: Vec::new() };
+    }
+
+    fn decode(&mut self, received: &[u8]) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        for &byte in received {
+            self.state = match (&self.state, byte) {
+                (TelnetState::Data, IAC) => TelnetState::Iac,
+                (TelnetState::Data, _) => {
+                    data.push(byte);
+                    TelnetState::Data
+                },
+                (TelnetState::Iac, IAC) => {
+                    data.push(IAC);
+                    TelnetState::Data
+                },
+                (TelnetState::Iac, WILL | WONT | DO | DONT) => TelnetState::Negotiation,
+                (TelnetState::Iac, SB) => {
+                    self.subnegotiation.clear();
+                    TelnetState::Subnegotiation
+                },
+                (TelnetState::Iac, _) => TelnetState::Data,
+                (TelnetState::Negotiation, _) => TelnetState::Data,
+                (TelnetState::Subnegotiation, IAC) => TelnetState::SubnegotiationIac,
+                (TelnetState::Subnegotiation, _) => {
+                    self.subnegotiation.push(byte);
+                    TelnetState::Subnegotiation
+                },
+                (TelnetState::SubnegotiationIac, SE) => {
+                    debug!("Received telnet subnegotiation: {:02X?}", &self.subnegotiation);
+                    TelnetState::Data
+                },
+                (TelnetState::SubnegotiationIac, _) => {
+                    self.subnegotiation.push(byte);
+                    TelnetState::Subnegotiation
+                },
+            };
+        }
+        return data;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_interface::{CameraInterface, SerialCameraConnection};
+    use crate::camera_interface::fake::FakeCamera;
+    use crate::camera_interface::messaging::{CameraCommand, EXPECTED_UNIT_INQUIRY_RESPONSE, OK_RESPONSE};
+    use crate::camera_operations;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Starts a stand-in serial server on the loopback interface. The given function is called
+    /// with the accepted connection.
+    fn start_server<F, R>(serve: F) -> (String, thread::JoinHandle<R>)
+            where F: FnOnce(&mut TcpStream) -> R + Send + 'static, R: Send + 'static {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            return serve(&mut stream);
+        });
+        return (address, handle);
+    }
+
+    fn read_from(stream: &mut TcpStream, length: usize) -> Vec<u8> {
+        let mut buffer = vec![0u8; length];
+        stream.read_exact(&mut buffer).unwrap();
+        return buffer;
+    }
+
+    /// Answers the session start and the memory reads from the given fake camera, until the
+    /// connection is closed. Returns the commands that the fake camera received.
+    fn serve_fake_camera(stream: &mut TcpStream, mut camera: FakeCamera) -> Vec<CameraCommand> {
+        read_from(stream, CameraCommand::Wakeup.get_bytes().len());
+        read_from(stream, CameraCommand::UnitInquiry.get_bytes().len());
+        let camera_info = camera.start_new_session().unwrap();
+        let mut response = format!("{}{}", camera_info.unit_code, camera_info.model_name).into_bytes();
+        response.extend([0x00, 0x03, 0x06]);
+        stream.write_all(&response).unwrap();
+
+        let read_command_length = CameraCommand::ReadMemory { memory_space: 0, address: 0, length: 0 }.get_bytes().len();
+        let mut command = vec![0u8; read_command_length];
+        while stream.read_exact(&mut command).is_ok() {
+            let address = u16::from_be_bytes([command[4], command[5]]);
+            let length = command[7];
+            camera.send_command(&CameraCommand::ReadMemory { memory_space: command[3], address, length }).unwrap();
+            stream.write_all(&camera.expect_data_packet(length).unwrap().serialize()).unwrap();
+        }
+        return camera.sent_commands;
+    }
+
+    #[test]
+    fn telnet_commands_should_be_removed_from_received_bytes() {
+        let mut decoder = TelnetDecoder::new();
+        let received = [0x01, IAC, WILL, COM_PORT_OPTION, 0x02, IAC, IAC, 0x03,
+                        IAC, SB, COM_PORT_OPTION, 101, 0x00, 0x00, 0x04, 0xB0, IAC, SE, 0x04];
+        assert_eq!(vec![0x01, 0x02, IAC, 0x03, 0x04], decoder.decode(&received));
+    }
+
+    #[test]
+    fn telnet_commands_split_between_reads_should_be_removed() {
+        let mut decoder = TelnetDecoder::new();
+        assert_eq!(vec![0x01], decoder.decode(&[0x01, IAC]));
+        assert_eq!(vec![0x02], decoder.decode(&[DO, BINARY_OPTION, 0x02, IAC, SB, COM_PORT_OPTION]));
+        assert!(decoder.decode(&[101, IAC]).is_empty());
+        assert_eq!(vec![0x03], decoder.decode(&[SE, 0x03, IAC]));
+        assert_eq!(vec![IAC], decoder.decode(&[IAC]));
+    }
+
+    #[test]
+    fn iac_bytes_should_be_escaped() {
+        assert_eq!(vec![0x01, IAC, IAC, 0x02], escape_iac(&[0x01, IAC, 0x02]));
+    }
+
+    #[test]
+    fn raw_connection_should_start_a_session_with_the_camera() {
+        let (address, server) = start_server(|stream| {
+            let mut received = read_from(stream, CameraCommand::Wakeup.get_bytes().len());
+            received.extend(read_from(stream, CameraCommand::UnitInquiry.get_bytes().len()));
+            stream.write_all(EXPECTED_UNIT_INQUIRY_RESPONSE).unwrap();
+            return received;
+        });
+
+        let serial = TcpConnection::new(&address, NetworkProtocol::Raw, Duration::from_secs(2), 1200).unwrap();
+        let mut camera = SerialCameraConnection::new(serial);
+        assert_eq!("F90X/N90S", camera.start_new_session().unwrap().model_name);
+
+        let mut expected = CameraCommand::Wakeup.get_bytes();
+        expected.extend(CameraCommand::UnitInquiry.get_bytes());
+        assert_eq!(expected, server.join().unwrap());
+    }
+
+    #[test]
+    fn memory_should_be_read_from_a_camera_behind_a_serial_server() {
+        let (address, server) = start_server(|stream| serve_fake_camera(stream, FakeCamera::with_address_pattern()));
+
+        let mut camera = camera_operations::open_session(&format!("tcp://{}", address), false).unwrap();
+        assert_eq!(vec![0x34, 0x35, 0x36], camera_operations::read_memory(&mut camera, 0, 0x1234, 3).unwrap());
+        drop(camera);
+
+        let expected = vec![
+            CameraCommand::Wakeup,
+            CameraCommand::UnitInquiry,
+            CameraCommand::ReadMemory { memory_space: 0, address: 0x1234, length: 3 },
+        ];
+        assert_eq!(expected, server.join().unwrap());
+    }
+
+    #[test]
+    fn raw_connection_should_not_change_baud_rate() {
+        let (address, _server) = start_server(|_| ());
+
+        let mut serial = TcpConnection::new(&address, NetworkProtocol::Raw, Duration::from_secs(2), 1200).unwrap();
+        assert!(!serial.can_change_baud_rate());
+        assert!(serial.set_baud_rate(9600).is_err());
+    }
+
+    #[test]
+    fn rfc2217_connection_should_change_baud_rate_remotely() {
+        let set_baud_rate_9600 = [IAC, SB, COM_PORT_OPTION, SET_BAUDRATE, 0x00, 0x00, 0x25, 0x80, IAC, SE];
+        let (address, server) = start_server(move |stream| {
+            // Negotiation and the initial serial settings
+            let initial_messages_length = 9 + 7 + 7 + 7 + 10;
+            read_from(stream, initial_messages_length);
+            let received = read_from(stream, set_baud_rate_9600.len());
+            stream.write_all(&[IAC, SB, COM_PORT_OPTION, 101, 0x00, 0x00, 0x25, 0x80, IAC, SE]).unwrap();
+            stream.write_all(OK_RESPONSE).unwrap();
+            return received;
+        });
+
+        let serial = TcpConnection::new(&address, NetworkProtocol::Rfc2217, Duration::from_secs(2), 1200).unwrap();
+        let mut camera = SerialCameraConnection::new(serial);
+        camera.serial.set_baud_rate(9600).unwrap();
+        assert!(camera.expect_ok_response().is_ok());
+        assert_eq!(set_baud_rate_9600.to_vec(), server.join().unwrap());
+    }
+
+    #[test]
+    fn rfc2217_connection_should_escape_written_data() {
+        let (address, server) = start_server(|stream| {
+            let initial_messages_length = 9 + 7 + 7 + 7 + 10;
+            read_from(stream, initial_messages_length);
+            return read_from(stream, 4);
+        });
+
+        let mut serial = TcpConnection::new(&address, NetworkProtocol::Rfc2217, Duration::from_secs(2), 1200).unwrap();
+        serial.write(&vec![0x01, IAC, 0x02]).unwrap();
+        assert_eq!(vec![0x01, IAC, IAC, 0x02], server.join().unwrap());
+    }
+}
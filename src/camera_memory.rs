@@ -0,0 +1,197 @@
+use crate::camera_interface::CameraInterface;
+use crate::camera_operations;
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Size of a memory space.
+const MEMORY_SPACE_SIZE: u64 = 0x10000;
+
+fn to_io_error(error: anyhow::Error) -> io::Error {
+    return io::Error::other(format!("{:#}", error));
+}
+
+/// A memory space of the camera as a file, with [Read], [Seek] and [Write].
+///
+/// Reads are done in [camera_operations::MAX_READ_LENGTH] byte chunks, and read ahead so that
+/// small reads don't each send a command. Writes are done in chunks of at most 255 bytes, and only
+/// the "0" memory space can be written.
+///
+/// ```no_run
+/// use f90x_tool::camera_memory::CameraMemory;
+/// use f90x_tool::camera_operations;
+/// use std::io::{Read, Seek, SeekFrom};
+///
+/// let mut camera = camera_operations::open_session(&String::from("/dev/ttyUSB0"), true)?;
+/// let mut memory = CameraMemory::new(&mut camera, 0);
+/// memory.seek(SeekFrom::Start(0xFD40))?;
+/// let mut values = [0u8; 8];
+/// memory.read_exact(&mut values)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct CameraMemory<'a, T: CameraInterface> {
+    camera: &'a mut T,
+    memory_space: u8,
+    position: u64,
+    /// Bytes that were read ahead, starting from `buffer_address`.
+    buffer: Vec<u8>,
+    buffer_address: u64,
+    read_ahead: usize,
+}
+
+impl<'a, T: CameraInterface> CameraMemory<'a, T> {
+    /// The camera should have a started session.
+    pub fn new(camera: &'a mut T, memory_space: u8) -> CameraMemory<'a, T> {
+        return CameraMemory {
+            camera,
+            memory_space,
+            position: 0,
+            buffer: Vec::new(),
+            buffer_address: 0,
+            read_ahead: camera_operations::MAX_READ_LENGTH as usize,
+        };
+    }
+
+    /// Sets the minimum number of bytes that a read fetches from the camera. 0 reads only the
+    /// requested bytes.
+    pub fn set_read_ahead(&mut self, read_ahead: usize) {
+        self.read_ahead = read_ahead;
+        self.buffer.clear();
+    }
+
+    /// Drops the bytes that were read ahead, for example when the camera has changed them.
+    pub fn discard_buffer(&mut self) {
+        self.buffer.clear();
+    }
+
+    fn get_buffered(&self) -> &[u8] {
+        let buffer_end = self.buffer_address + self.buffer.len() as u64;
+        if self.position < self.buffer_address || self.position >= buffer_end {
+            return &[];
+        }
+        return &self.buffer[(self.position - self.buffer_address) as usize..];
+    }
+}
+
+impl<T: CameraInterface> Read for CameraMemory<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= MEMORY_SPACE_SIZE || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.get_buffered().is_empty() {
+            let length = buf.len().max(self.read_ahead).min((MEMORY_SPACE_SIZE - self.position) as usize);
+            self.buffer = camera_operations::read_memory_range(self.camera, self.memory_space, self.position as u16, length)
+                    .map_err(to_io_error)?;
+            self.buffer_address = self.position;
+        }
+        let buffered = self.get_buffered();
+        let length = buffered.len().min(buf.len());
+        buf[..length].copy_from_slice(&buffered[..length]);
+        self.position += length as u64;
+        return Ok(length);
+    }
+}
+
+impl<T: CameraInterface> Write for CameraMemory<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.memory_space != 0 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Only the \"0\" memory space can be written"));
+        }
+        if self.position >= MEMORY_SPACE_SIZE || buf.is_empty() {
+            return Ok(0);
+        }
+        let length = buf.len().min(u8::MAX as usize).min((MEMORY_SPACE_SIZE - self.position) as usize);
+        camera_operations::write_memory(self.camera, self.position as u16, buf[..length].to_vec()).map_err(to_io_error)?;
+        self.buffer.clear();
+        self.position += length as u64;
+        return Ok(length);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+impl<T: CameraInterface> Seek for CameraMemory<'_, T> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match position {
+            SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(self.position);
+            },
+            SeekFrom::End(offset) => (MEMORY_SPACE_SIZE, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        self.position = base.checked_add_signed(offset)
+                .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek to a negative position"))?;
+        return Ok(self.position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_interface::fake::FakeCamera;
+
+    #[test]
+    fn small_reads_should_be_served_from_the_read_ahead() {
+        let mut fake_camera = FakeCamera::with_address_pattern();
+        let mut memory = CameraMemory::new(&mut fake_camera, 0);
+
+        memory.seek(SeekFrom::Start(0xFD40)).unwrap();
+        let mut values = [0u8; 2];
+        memory.read_exact(&mut values).unwrap();
+        assert_eq!([0x40, 0x41], values);
+        memory.read_exact(&mut values).unwrap();
+        assert_eq!([0x42, 0x43], values);
+        assert_eq!(1, fake_camera.get_reads().len());
+    }
+
+    #[test]
+    fn large_reads_should_be_chunked() {
+        let mut fake_camera = FakeCamera::with_address_pattern();
+        let mut memory = CameraMemory::new(&mut fake_camera, 0);
+        memory.set_read_ahead(0);
+
+        let mut values = vec![0u8; 200];
+        memory.read_exact(&mut values).unwrap();
+        assert_eq!(199, values[199]);
+        assert_eq!(4, fake_camera.get_reads().len());
+    }
+
+    #[test]
+    fn reading_at_the_end_of_the_memory_space_should_be_end_of_file() {
+        let mut fake_camera = FakeCamera::with_address_pattern();
+        let mut memory = CameraMemory::new(&mut fake_camera, 0);
+
+        memory.seek(SeekFrom::End(-3)).unwrap();
+        let mut values = Vec::new();
+        memory.read_to_end(&mut values).unwrap();
+        assert_eq!(vec![0xFD, 0xFE, 0xFF], values);
+        assert!(memory.seek(SeekFrom::Current(-0x10001)).is_err());
+    }
+
+    #[test]
+    fn writes_should_discard_the_read_ahead() {
+        let mut fake_camera = FakeCamera::with_address_pattern();
+        let mut memory = CameraMemory::new(&mut fake_camera, 0);
+
+        let mut value = [0u8; 1];
+        memory.seek(SeekFrom::Start(0x1000)).unwrap();
+        memory.read_exact(&mut value).unwrap();
+        memory.write_all(&vec![0xAA; 300]).unwrap();
+        assert_eq!(0x1001 + 300, memory.stream_position().unwrap());
+        memory.seek(SeekFrom::Start(0x1001)).unwrap();
+        memory.read_exact(&mut value).unwrap();
+        assert_eq!([0xAA], value);
+        assert_eq!(2, fake_camera.get_reads().len());
+        assert_eq!(vec![0xAA; 300], fake_camera.memory[0x1001..0x1001 + 300].to_vec());
+    }
+
+    #[test]
+    fn other_memory_spaces_should_not_be_written() {
+        let mut fake_camera = FakeCamera::with_address_pattern();
+        let mut memory = CameraMemory::new(&mut fake_camera, 1);
+
+        assert!(memory.write(&[0x01]).is_err());
+    }
+}
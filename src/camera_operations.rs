@@ -0,0 +1,280 @@
+use crate::camera_interface::{self, CameraInterface, SerialCameraConnection, SerialConnection, SerialInterface};
+use crate::camera_interface::messaging::CameraCommand;
+use crate::camera_interface::models::{CameraInfo, UnsupportedModelError};
+
+use anyhow::{Context, Result, anyhow};
+use log::debug;
+use std::time::Duration;
+
+#[cfg(test)]
+use mockall::{predicate::*, Sequence};
+
+/// Camera connection over a serial device or a serial server.
+pub type SerialCamera = SerialCameraConnection<Box<dyn SerialInterface + Send>>;
+
+/// Opens the given serial device and starts a new session with the camera. See
+/// [camera_interface::open_connection] for the accepted devices.
+///
+/// If `use_fast_session` is set, the session is upgraded to the fast BAUD rate of the camera. In
+/// that case [end_session] should be called when done.
+pub fn open_session(serial_device: &String, use_fast_session: bool) -> Result<SerialCamera> {
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut camera = SerialCameraConnection::new(serial);
+    camera.start_new_session()?;
+    if use_fast_session {
+        camera.upgrade_to_fast_session()?;
+    }
+    return Ok(camera);
+}
+
+/// A serial device that has a camera attached.
+pub struct DetectedCamera {
+    pub serial_device: String,
+    pub camera_info: CameraInfo,
+    /// Whether there is a profile for the model. Other models answer the unit inquiry, but can't
+    /// be used.
+    pub is_supported: bool,
+}
+
+/// Tries to start a session on every available serial port, and returns the ones that have a
+/// camera attached, also the ones whose model is not supported.
+///
+/// Ports that can't be opened, for example because they are busy, and ports that don't respond
+/// like a camera are skipped.
+pub fn detect_cameras() -> Result<Vec<DetectedCamera>> {
+    const DETECTION_TIMEOUT: Duration = Duration::from_millis(500);
+
+    let ports = serialport::available_ports().context("Could not list the serial ports")?;
+    let mut detected_cameras: Vec<DetectedCamera> = Vec::new();
+    for port in ports {
+        debug!("Looking for a camera at {}", port.port_name);
+        let result = SerialConnection::with_timeout(&port.port_name, DETECTION_TIMEOUT)
+                .and_then(|serial| SerialCameraConnection::new(serial).start_new_session());
+        match result {
+            Ok(camera_info) => {
+                detected_cameras.push(DetectedCamera { serial_device: port.port_name, camera_info, is_supported: true });
+            },
+            Err(error) => match error.downcast_ref::<UnsupportedModelError>() {
+                Some(unsupported) => detected_cameras.push(DetectedCamera {
+                    serial_device: port.port_name,
+                    camera_info: unsupported.camera_info.clone(),
+                    is_supported: false,
+                }),
+                None => debug!("Skipping {}: {:#}", port.port_name, error),
+            },
+        }
+    }
+    return Ok(detected_cameras);
+}
+
+/// Ends a session that was opened with [open_session].
+pub fn end_session<T: CameraInterface>(camera: &mut T, is_fast_session: bool) -> Result<()> {
+    if is_fast_session {
+        camera.end_fast_session()?;
+    }
+    return Ok(());
+}
+
+/// Reads given number of bytes from the given memory space, starting from the given address.
+pub fn read_memory<T: CameraInterface>(camera: &mut T, memory_space: u8, address: u16, length: u8) -> Result<Vec<u8>> {
+    camera.send_command(&CameraCommand::ReadMemory { memory_space, address, length })?;
+    let data_packet = camera.expect_data_packet(length)?;
+    return Ok(data_packet.bytes);
+}
+
+/// Maximum number of bytes to read with one [CameraCommand::ReadMemory] command.
+pub const MAX_READ_LENGTH: u8 = 64;
+
+/// Reads a memory range of any length, by reading it in chunks of [MAX_READ_LENGTH] bytes.
+pub fn read_memory_range<T: CameraInterface>(camera: &mut T, memory_space: u8, address: u16, length: usize) -> Result<Vec<u8>> {
+    if (address as usize) + length > 0x10000 {
+        return Err(anyhow!("Memory range {:04X}+{:X} is out of the address space", address, length));
+    }
+    let mut values: Vec<u8> = Vec::with_capacity(length);
+    while values.len() < length {
+        let chunk_address = address + (values.len() as u16);
+        let chunk_length = (length - values.len()).min(MAX_READ_LENGTH as usize) as u8;
+        values.append(&mut read_memory(camera, memory_space, chunk_address, chunk_length)?);
+    }
+    return Ok(values);
+}
+
+/// Writes the given values to the "0" memory space, starting from the given address.
+///
+/// At most 255 bytes can be written in one go.
+pub fn write_memory<T: CameraInterface>(camera: &mut T, address: u16, values: Vec<u8>) -> Result<()> {
+    if values.len() > (u8::MAX as usize) {
+        return Err(anyhow!("Too many values given."));
+    }
+    camera.send_command(&CameraCommand::WriteToMemory { address, values })?;
+    return camera.expect_ok_response();
+}
+
+/// Writes the given values like [write_memory], then reads them back and fails if the memory has
+/// other values.
+pub fn write_memory_verified<T: CameraInterface>(camera: &mut T, address: u16, values: Vec<u8>) -> Result<()> {
+    let length = values.len() as u8;
+    write_memory(camera, address, values.clone())?;
+    let actual_values = read_memory(camera, 0, address, length)?;
+    if actual_values != values {
+        return Err(anyhow!("Verification failed, wrote {:02X?} to {:04X} but read back {:02X?}",
+                           values, address, actual_values));
+    }
+    return Ok(());
+}
+
+/// Triggers auto-focus.
+pub fn autofocus<T: CameraInterface>(camera: &mut T) -> Result<()> {
+    camera.send_command(&CameraCommand::Focus)?;
+    return camera.expect_ok_response();
+}
+
+/// Releases the shutter.
+pub fn release_shutter<T: CameraInterface>(camera: &mut T) -> Result<()> {
+    camera.send_command(&CameraCommand::Shoot)?;
+    return camera.expect_ok_response();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_interface::MockCameraInterface;
+    use crate::camera_interface::messaging::DataPacket;
+
+    #[test]
+    fn read_memory_should_return_the_payload() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_send_command()
+                   .with(eq(CameraCommand::ReadMemory {memory_space: 1, address: 0x1234, length: 2}))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|_| Ok(()));
+        mock_camera.expect_expect_data_packet()
+                   .with(eq(2))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|_| Ok(DataPacket {bytes: vec![0xAB, 0xCD]}));
+
+        assert_eq!(vec![0xAB, 0xCD], read_memory(&mut mock_camera, 1, 0x1234, 2).unwrap());
+    }
+
+    #[test]
+    fn read_memory_range_should_read_in_chunks() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_send_command()
+                   .with(eq(CameraCommand::ReadMemory {memory_space: 0, address: 0xFFB0, length: MAX_READ_LENGTH}))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|_| Ok(()));
+        mock_camera.expect_expect_data_packet()
+                   .with(eq(MAX_READ_LENGTH))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|length| Ok(DataPacket {bytes: vec![0x01; length as usize]}));
+        mock_camera.expect_send_command()
+                   .with(eq(CameraCommand::ReadMemory {memory_space: 0, address: 0xFFF0, length: 0x10}))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|_| Ok(()));
+        mock_camera.expect_expect_data_packet()
+                   .with(eq(0x10))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|length| Ok(DataPacket {bytes: vec![0x02; length as usize]}));
+
+        let values = read_memory_range(&mut mock_camera, 0, 0xFFB0, 0x50).unwrap();
+        assert_eq!(0x50, values.len());
+        assert_eq!(0x02, values[0x40]);
+    }
+
+    #[test]
+    fn read_memory_range_outside_the_address_space_should_be_error() {
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_send_command().times(0);
+
+        assert!(read_memory_range(&mut mock_camera, 0, 0xFFF0, 0x11).is_err());
+    }
+
+    #[test]
+    fn write_memory_should_expect_ok_response() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_send_command()
+                   .with(eq(CameraCommand::WriteToMemory {address: 0x1234, values: vec![0x01, 0x02]}))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|_| Ok(()));
+        mock_camera.expect_expect_ok_response()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Err(anyhow!("")));
+
+        assert!(write_memory(&mut mock_camera, 0x1234, vec![0x01, 0x02]).is_err());
+    }
+
+    fn expect_verified_write(mock_camera: &mut MockCameraInterface, read_back: Vec<u8>) {
+        let mut sequence = Sequence::new();
+        mock_camera.expect_send_command()
+                   .with(eq(CameraCommand::WriteToMemory {address: 0x1234, values: vec![0x01, 0x02]}))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|_| Ok(()));
+        mock_camera.expect_expect_ok_response()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Ok(()));
+        mock_camera.expect_send_command()
+                   .with(eq(CameraCommand::ReadMemory {memory_space: 0, address: 0x1234, length: 2}))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|_| Ok(()));
+        mock_camera.expect_expect_data_packet()
+                   .with(eq(2))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(move |_| Ok(DataPacket {bytes: read_back.clone()}));
+    }
+
+    #[test]
+    fn verified_write_should_read_back_the_values() {
+        let mut mock_camera = MockCameraInterface::new();
+        expect_verified_write(&mut mock_camera, vec![0x01, 0x02]);
+
+        assert!(write_memory_verified(&mut mock_camera, 0x1234, vec![0x01, 0x02]).is_ok());
+    }
+
+    #[test]
+    fn verified_write_with_other_values_read_back_should_be_error() {
+        let mut mock_camera = MockCameraInterface::new();
+        expect_verified_write(&mut mock_camera, vec![0x01, 0x00]);
+
+        assert!(write_memory_verified(&mut mock_camera, 0x1234, vec![0x01, 0x02]).is_err());
+    }
+
+    #[test]
+    fn write_memory_with_too_many_values_should_not_send_anything() {
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_send_command().times(0);
+
+        assert!(write_memory(&mut mock_camera, 0x1234, vec![0x00; 256]).is_err());
+    }
+
+    #[test]
+    fn release_shutter_should_send_shoot_command() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_send_command()
+                   .with(eq(CameraCommand::Shoot))
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|_| Ok(()));
+        mock_camera.expect_expect_ok_response()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Ok(()));
+
+        assert!(release_shutter(&mut mock_camera).is_ok());
+    }
+}
@@ -0,0 +1,256 @@
+use crate::camera_interface::CameraInterface;
+use crate::camera_interface::models::{ExposureCompensationSetting, SettingLocation, SettingsMap};
+use crate::camera_operations;
+pub use crate::settings_types::{AfMode, Aperture, ExposureMode, FilmAdvanceMode, FlashSyncMode, MeteringMode, ShutterSpeed};
+
+use anyhow::{Result, anyhow};
+use std::fmt;
+
+#[cfg(test)]
+use mockall::{predicate::*, Sequence};
+
+pub(crate) fn read_setting<T: CameraInterface, V: Copy + fmt::Debug>(
+        camera: &mut T,
+        name: &str,
+        location: &Option<SettingLocation<V>>) -> Result<V> {
+    let location = location.as_ref().ok_or(anyhow!("The {} is not mapped for this camera model", name))?;
+    let value = camera_operations::read_memory(camera, 0, location.address, 1)?[0];
+    return location.values.iter()
+            .find(|(stored_value, _)| *stored_value == value)
+            .map(|(_, setting)| *setting)
+            .ok_or(anyhow!("Unknown {} value {:02X}", name, value));
+}
+
+fn write_setting<T: CameraInterface, V: PartialEq + fmt::Debug>(
+        camera: &mut T,
+        name: &str,
+        location: &Option<SettingLocation<V>>,
+        setting: V) -> Result<()> {
+    let location = location.as_ref().ok_or(anyhow!("The {} is not mapped for this camera model", name))?;
+    let value = location.values.iter()
+            .find(|(_, known_setting)| *known_setting == setting)
+            .map(|(stored_value, _)| *stored_value)
+            .ok_or(anyhow!("{:?} is not a valid {} for this camera model", setting, name))?;
+    return camera_operations::write_memory_verified(camera, location.address, vec![value]);
+}
+
+fn get_compensation_setting(settings_map: &SettingsMap) -> Result<&ExposureCompensationSetting> {
+    return settings_map.exposure_compensation.as_ref()
+            .ok_or(anyhow!("The exposure compensation is not mapped for this camera model"));
+}
+
+/// Typed access to the camera settings, using the addresses and encodings of a [SettingsMap].
+/// Every written setting is read back, and a setting that reads back differently is an error.
+///
+/// The settings that are not mapped for the model are an error, see
+/// [SettingsMap::get_mapped_names]. None of the settings is mapped for the F90x yet.
+pub struct CameraSettings<'a, T: CameraInterface> {
+    camera: &'a mut T,
+    settings_map: &'a SettingsMap,
+}
+
+impl<'a, T: CameraInterface> CameraSettings<'a, T> {
+    /// Usually the settings map comes from the profile of the camera model, see
+    /// [crate::camera_interface::models::CameraInfo::get_profile].
+    pub fn new(camera: &'a mut T, settings_map: &'a SettingsMap) -> CameraSettings<'a, T> {
+        return CameraSettings { camera, settings_map };
+    }
+
+    pub fn get_exposure_mode(&mut self) -> Result<ExposureMode> {
+        return read_setting(self.camera, "exposure mode", &self.settings_map.exposure_mode);
+    }
+
+    pub fn set_exposure_mode(&mut self, exposure_mode: ExposureMode) -> Result<()> {
+        return write_setting(self.camera, "exposure mode", &self.settings_map.exposure_mode, exposure_mode);
+    }
+
+    pub fn get_shutter_speed(&mut self) -> Result<ShutterSpeed> {
+        return read_setting(self.camera, "shutter speed", &self.settings_map.shutter_speed);
+    }
+
+    pub fn set_shutter_speed(&mut self, shutter_speed: ShutterSpeed) -> Result<()> {
+        return write_setting(self.camera, "shutter speed", &self.settings_map.shutter_speed, shutter_speed);
+    }
+
+    pub fn get_aperture(&mut self) -> Result<Aperture> {
+        return read_setting(self.camera, "aperture", &self.settings_map.aperture);
+    }
+
+    pub fn set_aperture(&mut self, aperture: Aperture) -> Result<()> {
+        return write_setting(self.camera, "aperture", &self.settings_map.aperture, aperture);
+    }
+
+    /// Returns the exposure compensation in stops.
+    pub fn get_exposure_compensation(&mut self) -> Result<f64> {
+        let setting = get_compensation_setting(self.settings_map)?;
+        let value = camera_operations::read_memory(self.camera, 0, setting.address, 1)?[0];
+        return Ok(setting.value_to_stops(value));
+    }
+
+    /// Sets the exposure compensation in stops. It should be a multiple of the step of the model.
+    pub fn set_exposure_compensation(&mut self, stops: f64) -> Result<()> {
+        let setting = get_compensation_setting(self.settings_map)?;
+        let value = setting.stops_to_value(stops)?;
+        return camera_operations::write_memory_verified(self.camera, setting.address, vec![value]);
+    }
+
+    pub fn get_metering_mode(&mut self) -> Result<MeteringMode> {
+        return read_setting(self.camera, "metering mode", &self.settings_map.metering_mode);
+    }
+
+    pub fn set_metering_mode(&mut self, metering_mode: MeteringMode) -> Result<()> {
+        return write_setting(self.camera, "metering mode", &self.settings_map.metering_mode, metering_mode);
+    }
+
+    pub fn get_af_mode(&mut self) -> Result<AfMode> {
+        return read_setting(self.camera, "AF mode", &self.settings_map.af_mode);
+    }
+
+    pub fn set_af_mode(&mut self, af_mode: AfMode) -> Result<()> {
+        return write_setting(self.camera, "AF mode", &self.settings_map.af_mode, af_mode);
+    }
+
+    pub fn get_film_advance_mode(&mut self) -> Result<FilmAdvanceMode> {
+        return read_setting(self.camera, "film advance mode", &self.settings_map.film_advance_mode);
+    }
+
+    pub fn set_film_advance_mode(&mut self, film_advance_mode: FilmAdvanceMode) -> Result<()> {
+        return write_setting(self.camera, "film advance mode", &self.settings_map.film_advance_mode, film_advance_mode);
+    }
+
+    pub fn get_flash_sync_mode(&mut self) -> Result<FlashSyncMode> {
+        return read_setting(self.camera, "flash sync mode", &self.settings_map.flash_sync_mode);
+    }
+
+    pub fn set_flash_sync_mode(&mut self, flash_sync_mode: FlashSyncMode) -> Result<()> {
+        return write_setting(self.camera, "flash sync mode", &self.settings_map.flash_sync_mode, flash_sync_mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_interface::MockCameraInterface;
+    use crate::camera_interface::messaging::{CameraCommand, DataPacket};
+    use crate::camera_interface::models::F90X_PROFILE;
+
+    // Made up addresses and values, as the real ones are not mapped yet.
+    const TEST_SETTINGS_MAP: SettingsMap = SettingsMap {
+        exposure_mode: None,
+        shutter_speed: Some(SettingLocation {
+            address: 0x1000,
+            values: &[(0x10, ShutterSpeed::Bulb), (0x20, ShutterSpeed::Seconds(2)), (0x30, ShutterSpeed::Fraction(1, 250))],
+        }),
+        aperture: None,
+        exposure_compensation: Some(ExposureCompensationSetting { address: 0x1001, steps_per_stop: 3 }),
+        metering_mode: Some(SettingLocation {
+            address: 0x1002,
+            values: &[(0x00, MeteringMode::Matrix), (0x01, MeteringMode::CenterWeighted)],
+        }),
+        af_mode: None,
+        film_advance_mode: None,
+        flash_sync_mode: None,
+    };
+
+    fn expect_read(mock_camera: &mut MockCameraInterface, sequence: &mut Sequence, address: u16, value: u8) {
+        mock_camera.expect_send_command()
+                   .with(eq(CameraCommand::ReadMemory { memory_space: 0, address, length: 1 }))
+                   .times(1)
+                   .in_sequence(sequence)
+                   .returning(|_| Ok(()));
+        mock_camera.expect_expect_data_packet()
+                   .with(eq(1))
+                   .times(1)
+                   .in_sequence(sequence)
+                   .returning(move |_| Ok(DataPacket { bytes: vec![value] }));
+    }
+
+    #[test]
+    fn shutter_speed_should_be_read_as_typed_value() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        expect_read(&mut mock_camera, &mut sequence, 0x1000, 0x30);
+
+        let mut settings = CameraSettings::new(&mut mock_camera, &TEST_SETTINGS_MAP);
+        assert_eq!(ShutterSpeed::Fraction(1, 250), settings.get_shutter_speed().unwrap());
+    }
+
+    #[test]
+    fn unknown_stored_value_should_be_error() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        expect_read(&mut mock_camera, &mut sequence, 0x1002, 0x07);
+
+        let mut settings = CameraSettings::new(&mut mock_camera, &TEST_SETTINGS_MAP);
+        assert!(settings.get_metering_mode().is_err());
+    }
+
+    fn expect_write(mock_camera: &mut MockCameraInterface, sequence: &mut Sequence, address: u16, value: u8) {
+        mock_camera.expect_send_command()
+                   .with(eq(CameraCommand::WriteToMemory { address, values: vec![value] }))
+                   .times(1)
+                   .in_sequence(sequence)
+                   .returning(|_| Ok(()));
+        mock_camera.expect_expect_ok_response()
+                   .times(1)
+                   .in_sequence(sequence)
+                   .returning(|| Ok(()));
+    }
+
+    #[test]
+    fn setting_should_be_written_with_its_stored_value() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        expect_write(&mut mock_camera, &mut sequence, 0x1002, 0x01);
+        expect_read(&mut mock_camera, &mut sequence, 0x1002, 0x01);
+
+        let mut settings = CameraSettings::new(&mut mock_camera, &TEST_SETTINGS_MAP);
+        settings.set_metering_mode(MeteringMode::CenterWeighted).unwrap();
+    }
+
+    #[test]
+    fn setting_should_be_error_if_it_is_not_read_back() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        expect_write(&mut mock_camera, &mut sequence, 0x1002, 0x01);
+        expect_read(&mut mock_camera, &mut sequence, 0x1002, 0x00);
+
+        let mut settings = CameraSettings::new(&mut mock_camera, &TEST_SETTINGS_MAP);
+        assert!(settings.set_metering_mode(MeteringMode::CenterWeighted).is_err());
+    }
+
+    #[test]
+    fn settings_that_are_not_mapped_should_not_be_accessed() {
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_send_command().times(0);
+
+        let mut settings = CameraSettings::new(&mut mock_camera, &TEST_SETTINGS_MAP);
+        assert!(settings.get_exposure_mode().is_err());
+        assert!(settings.set_af_mode(AfMode::Continuous).is_err());
+        assert!(settings.set_metering_mode(MeteringMode::Spot).is_err());
+        assert!(settings.set_shutter_speed(ShutterSpeed::Fraction(1, 8000)).is_err());
+    }
+
+    #[test]
+    fn mapped_settings_should_be_listed() {
+        assert_eq!(vec!["shutter_speed", "exposure_compensation", "metering_mode"], TEST_SETTINGS_MAP.get_mapped_names());
+        assert!(F90X_PROFILE.settings_map.get_mapped_names().is_empty());
+    }
+
+    #[test]
+    fn exposure_compensation_should_be_in_stops() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        expect_read(&mut mock_camera, &mut sequence, 0x1001, 0xFE);
+
+        let mut settings = CameraSettings::new(&mut mock_camera, &TEST_SETTINGS_MAP);
+        assert_eq!(-2.0 / 3.0, settings.get_exposure_compensation().unwrap());
+    }
+
+    #[test]
+    fn values_should_be_displayed_like_on_the_camera() {
+        assert_eq!("1/250", ShutterSpeed::Fraction(1, 250).to_string());
+        assert_eq!("2s", ShutterSpeed::Seconds(2).to_string());
+        assert_eq!("f/5.6", Aperture(5.6).to_string());
+    }
+}
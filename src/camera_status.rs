@@ -0,0 +1,147 @@
+use crate::camera_interface::CameraInterface;
+use crate::camera_interface::models::{CameraProfile, FlagLocation};
+use crate::camera_operations;
+use crate::camera_settings::{self, AfMode, Aperture, CameraSettings, ExposureMode, FilmAdvanceMode, FlashSyncMode, MeteringMode, ShutterSpeed};
+use crate::shooting_data::{self, MemoHolderStatus};
+
+use anyhow::Result;
+
+/// A snapshot of the camera state. Values whose location is not mapped for the model are None.
+///
+/// For the F90x only the memo holder is mapped so far, as its status and settings maps are empty.
+#[derive(Debug)]
+pub struct CameraStatus {
+    pub model_name: &'static str,
+    pub frame_count: Option<u8>,
+    pub film_loaded: Option<bool>,
+    pub dx_iso: Option<u16>,
+    pub cpu_lens_mounted: Option<bool>,
+    pub focal_length_mm: Option<u16>,
+    pub max_aperture: Option<Aperture>,
+    /// Raw light meter reading.
+    pub meter_reading: Option<u8>,
+    pub exposure_mode: Option<ExposureMode>,
+    pub shutter_speed: Option<ShutterSpeed>,
+    pub aperture: Option<Aperture>,
+    pub exposure_compensation: Option<f64>,
+    pub metering_mode: Option<MeteringMode>,
+    pub af_mode: Option<AfMode>,
+    pub film_advance_mode: Option<FilmAdvanceMode>,
+    pub flash_sync_mode: Option<FlashSyncMode>,
+    pub memo_holder: MemoHolderStatus,
+}
+
+fn read_u8<T: CameraInterface>(camera: &mut T, address: u16) -> Result<u8> {
+    return Ok(camera_operations::read_memory(camera, 0, address, 1)?[0]);
+}
+
+fn read_flag<T: CameraInterface>(camera: &mut T, flag: &FlagLocation) -> Result<bool> {
+    let value = camera_operations::read_memory(camera, 0, flag.address, 1)?[0];
+    return Ok(value & flag.mask != 0);
+}
+
+fn read_little_endian_u16<T: CameraInterface>(camera: &mut T, address: u16) -> Result<u16> {
+    let bytes = camera_operations::read_memory(camera, 0, address, 2)?;
+    return Ok(u16::from_le_bytes([bytes[0], bytes[1]]));
+}
+
+/// Reads everything that is known about the current state of the camera.
+pub fn get_camera_status<T: CameraInterface>(camera: &mut T, profile: &CameraProfile) -> Result<CameraStatus> {
+    let status_map = &profile.status_map;
+    let settings_map = &profile.settings_map;
+
+    // Only the mapped values are read, so that communication errors are still errors.
+    let frame_count = status_map.frame_counter.map(|address| read_u8(camera, address)).transpose()?;
+    let film_loaded = status_map.film_loaded.as_ref().map(|flag| read_flag(camera, flag)).transpose()?;
+    let dx_iso = status_map.dx_iso.map(|address| read_little_endian_u16(camera, address)).transpose()?;
+    let cpu_lens_mounted = status_map.cpu_lens_mounted.as_ref().map(|flag| read_flag(camera, flag)).transpose()?;
+    let focal_length_mm = status_map.focal_length.map(|address| read_little_endian_u16(camera, address)).transpose()?;
+    let max_aperture = status_map.max_aperture.as_ref()
+            .map(|_| camera_settings::read_setting(camera, "maximum aperture", &status_map.max_aperture))
+            .transpose()?;
+    let meter_reading = status_map.meter_reading.map(|address| read_u8(camera, address)).transpose()?;
+
+    let mut settings = CameraSettings::new(camera, settings_map);
+    let exposure_mode = settings_map.exposure_mode.as_ref().map(|_| settings.get_exposure_mode()).transpose()?;
+    let shutter_speed = settings_map.shutter_speed.as_ref().map(|_| settings.get_shutter_speed()).transpose()?;
+    let aperture = settings_map.aperture.as_ref().map(|_| settings.get_aperture()).transpose()?;
+    let exposure_compensation = settings_map.exposure_compensation.as_ref()
+            .map(|_| settings.get_exposure_compensation())
+            .transpose()?;
+    let metering_mode = settings_map.metering_mode.as_ref().map(|_| settings.get_metering_mode()).transpose()?;
+    let af_mode = settings_map.af_mode.as_ref().map(|_| settings.get_af_mode()).transpose()?;
+    let film_advance_mode = settings_map.film_advance_mode.as_ref().map(|_| settings.get_film_advance_mode()).transpose()?;
+    let flash_sync_mode = settings_map.flash_sync_mode.as_ref().map(|_| settings.get_flash_sync_mode()).transpose()?;
+
+    return Ok(CameraStatus {
+        model_name: profile.model_name,
+        frame_count,
+        film_loaded,
+        dx_iso,
+        cpu_lens_mounted,
+        focal_length_mm,
+        max_aperture,
+        meter_reading,
+        exposure_mode,
+        shutter_speed,
+        aperture,
+        exposure_compensation,
+        metering_mode,
+        af_mode,
+        film_advance_mode,
+        flash_sync_mode,
+        memo_holder: shooting_data::get_memo_holder_status(camera, &profile.memory_map)?,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_interface::fake::FakeCamera;
+    use crate::camera_interface::models::{F90X_PROFILE, StatusMap};
+
+    fn f90x_memory() -> Vec<u8> {
+        let mut memory = vec![0x00; 0x10000];
+        memory[0xFD00..0xFD04].copy_from_slice(&[0x00, 0x10, 0x00, 0x20]);
+        memory[0xFD40] = 0x45;
+        memory[0xFD42..0xFD48].copy_from_slice(&[0x00, 0x10, 0x00, 0x10, 0x00, 0x10]);
+        return memory;
+    }
+
+    #[test]
+    fn values_that_are_not_mapped_should_be_none() {
+        let mut fake_camera = FakeCamera::new(f90x_memory());
+
+        let status = get_camera_status(&mut fake_camera, &F90X_PROFILE).unwrap();
+        assert_eq!(None, status.frame_count);
+        assert_eq!(None, status.exposure_mode);
+        assert_eq!(0x1000, status.memo_holder.ring_buffer.start);
+    }
+
+    #[test]
+    fn mapped_values_should_be_read() {
+        // Made up addresses, as the real ones are not mapped yet.
+        let profile = CameraProfile {
+            status_map: StatusMap {
+                frame_counter: Some(0x0100),
+                film_loaded: Some(FlagLocation { address: 0x0101, mask: 0x04 }),
+                dx_iso: Some(0x0102),
+                cpu_lens_mounted: Some(FlagLocation { address: 0x0101, mask: 0x08 }),
+                focal_length: None,
+                max_aperture: None,
+                meter_reading: None,
+            },
+            ..F90X_PROFILE
+        };
+        let mut memory = f90x_memory();
+        memory[0x0100..0x0104].copy_from_slice(&[0x24, 0x05, 0x90, 0x01]);
+        let mut fake_camera = FakeCamera::new(memory);
+
+        let status = get_camera_status(&mut fake_camera, &profile).unwrap();
+        assert_eq!(Some(0x24), status.frame_count);
+        assert_eq!(Some(true), status.film_loaded);
+        assert_eq!(Some(false), status.cpu_lens_mounted);
+        assert_eq!(Some(400), status.dx_iso);
+        assert_eq!(None, status.focal_length_mm);
+    }
+}
@@ -1,82 +1,162 @@
-use crate::camera_interface::{SerialCameraConnection, CameraInterface, SerialConnection};
-use crate::camera_interface::messaging::CameraCommand;
+use f90x_tool::bracketing;
+use f90x_tool::camera_operations;
+use f90x_tool::camera_interface::{self, CameraInterface, SerialCameraConnection};
+use f90x_tool::camera_interface::messaging::CameraCommand;
+use f90x_tool::camera_interface::models::{CameraProfile, ExposureCompensationSetting};
+use f90x_tool::session::Session;
+
+use crate::address_map::AddressMap;
+use crate::journal::{self, Journal, JournaledCamera};
 
 use anyhow::{Result, anyhow};
+use std::io::{self, Write};
 
+/// Reads the given address, that can also be a name from the address map. The length and the
+/// memory space of a name are taken from the map, unless given.
 pub fn read_memory_in_new_session(
         serial_device: &String,
-        address: u16,
-        length: u8,
-        memory_space: u8,
+        address_map: &AddressMap,
+        address: &str,
+        length: Option<u8>,
+        memory_space: Option<u8>,
         use_fast_session: bool) -> Result<()> {
-    let serial = SerialConnection::new(&serial_device)?;
-    let mut camera = SerialCameraConnection::new(serial);
-    camera.start_new_session()?;
-    if use_fast_session {
-        camera.upgrade_to_fast_session()?;
+    let resolved = address_map.resolve(address)?;
+    let length = length.map_or_else(|| resolved.get_read_length(), Ok)?;
+    let memory_space = memory_space.unwrap_or(resolved.get_memory_space());
+    let entry = address_map.find_by_address(memory_space, resolved.address)
+            .filter(|entry| entry.size == length as u16);
+
+    let mut camera = camera_operations::open_session(serial_device, use_fast_session)?;
+
+    let values = camera_operations::read_memory(&mut camera, memory_space, resolved.address, length)?;
+    match entry {
+        Some(entry) => println!("{} = {}", entry.name, entry.decode(&values)),
+        None => println!("Memory value: {:02X?}", &values),
     }
 
-    camera.send_command(&CameraCommand::ReadMemory { memory_space, address, length })?;
-    let data_packet = camera.expect_data_packet(length)?;
-    println!("Memory value: {:02X?}", &data_packet.bytes);
+    camera_operations::end_session(&mut camera, use_fast_session)?;
+    return Ok(());
+}
 
-    if use_fast_session {
-        camera.end_fast_session()?;
-    }
+/// How a write is checked before it is done.
+pub struct WriteOptions {
+    /// Only print the command that would be sent.
+    pub dry_run: bool,
+    /// Write outside of the known safe locations without asking.
+    pub assume_yes: bool,
+    pub use_fast_session: bool,
+}
 
-    return Ok(());
+/// Asks a yes or no question on the terminal.
+pub fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    return Ok(matches!(answer.trim(), "y" | "Y" | "yes"));
 }
 
+/// Writes to the given address, that can also be a name from the address map. The values of a
+/// name can be given by the names in the map.
+///
+/// Writes to the protected locations of the map are refused, and writes outside of the known safe
+/// locations need a confirmation. The written values are read back to verify them, and the
+/// replaced values are recorded in the journal.
 pub fn write_memory_in_new_session(
         serial_device: &String,
-        address: u16,
-        values: Vec<u8>,
-        use_fast_session: bool) -> Result<()> {
-    if values.len() > (u8::MAX as usize) {
-        return Err(anyhow!("Too many values given."));
+        address_map: &AddressMap,
+        journal: &Journal,
+        address: &str,
+        values: &[String],
+        options: &WriteOptions) -> Result<()> {
+    let resolved = address_map.resolve(address)?;
+    let values = address_map.parse_values(&resolved, values)?;
+    if let Some(entry) = resolved.entry.filter(|entry| entry.space != 0) {
+        return Err(anyhow!("\"{}\" is in memory space {}, only the \"0\" memory space can be written", entry.name, entry.space));
     }
-    let serial = SerialConnection::new(&serial_device)?;
-    let mut camera = SerialCameraConnection::new(serial);
-    camera.start_new_session()?;
-    if use_fast_session {
-        camera.upgrade_to_fast_session()?;
+    if values.is_empty() || values.len() > (u8::MAX as usize) {
+        return Err(anyhow!("Give 1 to 255 values to write"));
     }
+    let range = (resolved.address as usize)..(resolved.address as usize) + values.len();
+    if range.end > 0x10000 {
+        return Err(anyhow!("The values don't fit in the address space"));
+    }
+    let is_safe = address_map.check_write(&range)?;
 
-    camera.send_command(&CameraCommand::WriteToMemory { address, values })?;
-    camera.expect_ok_response()?;
-    println!("Successfully written.");
-
-    if use_fast_session {
-        camera.end_fast_session()?;
+    if options.dry_run {
+        let command = CameraCommand::WriteToMemory { address: resolved.address, values };
+        let bytes: Vec<String> = command.get_bytes().iter().map(|byte| format!("{:02X}", byte)).collect();
+        println!("Would send: {}", bytes.join(" "));
+        return Ok(());
+    }
+    if !is_safe && !options.assume_yes {
+        let question = format!("{:04X}-{:04X} is not a known safe location. Write anyway?", range.start, range.end - 1);
+        if !confirm(&question)? {
+            return Err(anyhow!("Write cancelled"));
+        }
     }
 
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), options.use_fast_session);
+    session.start()?;
+    let result = journal::write_with_journal(&mut session, journal, address_map, resolved.address, values);
+    session.close()?;
+    result?;
+    println!("Successfully written and verified.");
     return Ok(());
 }
 
 pub fn autofocus_in_new_session(serial_device: &String) -> Result<()> {
-    let serial = SerialConnection::new(&serial_device)?;
-    let mut camera = SerialCameraConnection::new(serial);
-    camera.start_new_session()?;
-    camera.send_command(&CameraCommand::Focus)?;
-    camera.expect_ok_response()?;
+    let mut camera = camera_operations::open_session(serial_device, false)?;
+    camera_operations::autofocus(&mut camera)?;
 
     return Ok(());
 }
 
-pub fn release_shutter_in_new_session(serial_device: &String) -> Result<()> {
-    let serial = SerialConnection::new(&serial_device)?;
-    let mut camera = SerialCameraConnection::new(serial);
-    camera.start_new_session()?;
-    camera.send_command(&CameraCommand::Shoot)?;
-    camera.expect_ok_response()?;
+/// Returns the exposure compensation location of the profile, or else the "exposure_compensation"
+/// location of the address map, stored in `steps_per_stop` steps.
+fn get_exposure_compensation_setting(
+        profile: &CameraProfile,
+        address_map: &AddressMap,
+        steps_per_stop: u8) -> Result<ExposureCompensationSetting> {
+    if let Some(setting) = &profile.settings_map.exposure_compensation {
+        return Ok(ExposureCompensationSetting { address: setting.address, steps_per_stop: setting.steps_per_stop });
+    }
+    let entry = address_map.find_by_name("exposure_compensation").ok_or(anyhow!(
+            "The exposure compensation is not mapped for the {}. Add an \"exposure_compensation\" location with --map.",
+            profile.model_name))?;
+    if entry.space != 0 || entry.size != 1 {
+        return Err(anyhow!("\"exposure_compensation\" should be one byte in the \"0\" memory space"));
+    }
+    return Ok(ExposureCompensationSetting { address: entry.address, steps_per_stop });
+}
 
-    return Ok(());
+/// Releases the shutter once for each exposure compensation. The compensation writes are recorded
+/// in the journal.
+pub fn shoot_bracket_in_new_session(
+        serial_device: &String,
+        address_map: &AddressMap,
+        journal: &Journal,
+        stops: &[f64],
+        steps_per_stop: u8,
+        use_fast_session: bool) -> Result<()> {
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), use_fast_session);
+    let camera_info = session.start()?.clone();
+    let result = camera_info.get_profile()
+            .and_then(|profile| get_exposure_compensation_setting(profile, address_map, steps_per_stop))
+            .and_then(|setting| {
+                let mut camera = JournaledCamera::new(session.get_camera()?, journal, address_map, &camera_info.model_name);
+                return bracketing::shoot_bracket(&mut camera, &setting, stops, |stops| {
+                    println!("Released at {:+} stops", stops);
+                });
+            });
+    session.close()?;
+    return result;
 }
 
 pub fn read_and_print_memo_holder_info_in_new_session(serial_device: &String) -> Result<()> {
-    let serial = SerialConnection::new(&serial_device)?;
-    let mut camera = SerialCameraConnection::new(serial);
-    camera.start_new_session()?;
+    let mut camera = camera_operations::open_session(serial_device, false)?;
     camera.send_command(&CameraCommand::ReadMemoHolderInfo)?;
     let data_packet = camera.expect_data_packet(4)?;
     // TODO
@@ -88,3 +168,48 @@ pub fn read_and_print_memo_holder_info_in_new_session(serial_device: &String) ->
     return Ok(());
 }
 
+pub fn detect_and_print_cameras() -> Result<()> {
+    let detected_cameras = camera_operations::detect_cameras()?;
+    if detected_cameras.is_empty() {
+        println!("No camera found.");
+    }
+    for detected in detected_cameras {
+        if detected.is_supported {
+            println!("{}: {}", detected.serial_device, detected.camera_info.model_name);
+        } else {
+            println!("{}: {} (not supported)", detected.serial_device, detected.camera_info.model_name);
+        }
+    }
+
+    return Ok(());
+}
+
+/// Returns the first serial device that has a supported camera attached.
+pub fn find_serial_device() -> Result<String> {
+    let detected = camera_operations::detect_cameras()?
+            .into_iter()
+            .find(|detected| detected.is_supported)
+            .ok_or(anyhow!("Could not find a serial device with a camera attached"))?;
+    eprintln!("Using {} at {}", detected.camera_info.model_name, detected.serial_device);
+    return Ok(detected.serial_device);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use f90x_tool::camera_interface::models::F90X_PROFILE;
+
+    #[test]
+    fn exposure_compensation_should_come_from_the_address_map_if_the_profile_has_none() {
+        let address_map = AddressMap::load_with_defaults(None, &F90X_PROFILE).unwrap();
+        let error = get_exposure_compensation_setting(&F90X_PROFILE, &address_map, 3).unwrap_err();
+        assert!(error.to_string().starts_with("The exposure compensation is not mapped for the F90X/N90S"));
+
+        let address_map = AddressMap::parse("[[address]]\nname = \"exposure_compensation\"\naddress = 0x1234\n").unwrap();
+        let setting = get_exposure_compensation_setting(&F90X_PROFILE, &address_map, 2).unwrap();
+        assert_eq!(ExposureCompensationSetting { address: 0x1234, steps_per_stop: 2 }, setting);
+
+        let address_map = AddressMap::parse("[[address]]\nname = \"exposure_compensation\"\naddress = 0x1234\nsize = 2\n").unwrap();
+        assert!(get_exposure_compensation_setting(&F90X_PROFILE, &address_map, 3).is_err());
+    }
+}
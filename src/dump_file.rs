@@ -0,0 +1,143 @@
+use f90x_tool::camera_interface::{self, SerialCameraConnection};
+use f90x_tool::camera_interface::models::CameraInfo;
+use f90x_tool::memory_dump::{self, MemoryDump};
+use f90x_tool::session::Session;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{Local, SecondsFormat};
+use serde_json::{Value, json};
+use std::fs;
+use std::io::{ErrorKind, Write};
+
+/// Returns the path of the metadata file that is written next to a dump.
+pub fn get_metadata_path(dump_path: &str) -> String {
+    return format!("{}.json", dump_path);
+}
+
+/// Describes a dump: where it comes from, and which ranges could not be read.
+pub fn create_metadata(dump: &MemoryDump, camera_info: &CameraInfo, date: &str) -> Value {
+    let unreadable_ranges: Vec<Value> = dump.unreadable_ranges.iter()
+            .map(|range| json!({ "start": range.start, "end": range.end }))
+            .collect();
+    return json!({
+        "model": camera_info.model_name,
+        "unit_code": camera_info.unit_code,
+        "memory_space": dump.memory_space,
+        "start_address": dump.start_address,
+        "length": dump.data.len(),
+        "date": date,
+        "unreadable_ranges": unreadable_ranges,
+    });
+}
+
+fn get_number(metadata: &Value, key: &str) -> Result<u64> {
+    return metadata[key].as_u64().ok_or(anyhow!("Missing or invalid \"{}\" in the metadata", key));
+}
+
+/// Reads the dump from the metadata and the binary data. Without metadata, the dump is assumed
+/// to start from address 0 of the "0" memory space.
+pub fn parse_dump(data: Vec<u8>, metadata: Option<&Value>) -> Result<MemoryDump> {
+    let Some(metadata) = metadata else {
+        return Ok(MemoryDump { memory_space: 0, start_address: 0, data, unreadable_ranges: Vec::new() });
+    };
+    let mut unreadable_ranges = Vec::new();
+    for range in metadata["unreadable_ranges"].as_array().unwrap_or(&Vec::new()) {
+        unreadable_ranges.push((get_number(range, "start")? as usize)..(get_number(range, "end")? as usize));
+    }
+    return Ok(MemoryDump {
+        memory_space: u8::try_from(get_number(metadata, "memory_space")?)?,
+        start_address: u16::try_from(get_number(metadata, "start_address")?)?,
+        data,
+        unreadable_ranges,
+    });
+}
+
+/// Reads a dump file, and the metadata next to it if there is one. A missing metadata file is
+/// only warned about, but a metadata file that can't be read is an error.
+pub fn read_dump(dump_path: &str) -> Result<MemoryDump> {
+    let data = fs::read(dump_path).with_context(|| format!("Could not read {}", dump_path))?;
+    let metadata_path = get_metadata_path(dump_path);
+    let metadata: Option<Value> = match fs::read_to_string(&metadata_path) {
+        Ok(text) => Some(serde_json::from_str(&text).with_context(|| format!("Invalid metadata in {}", metadata_path))?),
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            eprintln!("Warning: No metadata file {}, assuming that {} starts from address 0 of memory space 0",
+                      metadata_path, dump_path);
+            None
+        },
+        Err(error) => return Err(error).with_context(|| format!("Could not read {}", metadata_path)),
+    };
+    return parse_dump(data, metadata.as_ref()).with_context(|| format!("Invalid metadata in {}", metadata_path));
+}
+
+/// Dumps a memory range in a fast session to a binary file, with the metadata in a JSON file
+/// next to it. The ranges that can't be read are zero in the binary file, and listed in the
+/// metadata.
+pub fn dump_to_file(serial_device: &String, memory_space: u8, start_address: u16, length: usize, out_file: &String) -> Result<()> {
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), true);
+    let camera_info = session.start()?.clone();
+
+    let dump = memory_dump::dump_memory(&mut session, memory_space, start_address, length, |done| {
+        eprint!("\rRead {:#X} of {:#X} bytes", done, length);
+        let _ = std::io::stderr().flush();
+    });
+    eprintln!();
+    session.close()?;
+    let dump = dump?;
+
+    fs::write(out_file, &dump.data).with_context(|| format!("Could not write {}", out_file))?;
+    let date = Local::now().to_rfc3339_opts(SecondsFormat::Secs, false);
+    let metadata = create_metadata(&dump, &camera_info, &date);
+    let metadata_path = get_metadata_path(out_file);
+    fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)? + "\n")
+            .with_context(|| format!("Could not write {}", metadata_path))?;
+
+    println!("Wrote {} bytes to {}, metadata to {}", dump.data.len(), out_file, metadata_path);
+    for range in &dump.unreadable_ranges {
+        println!("Could not read {:04X} - {:04X}", range.start, range.end - 1);
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn metadata_should_list_the_unreadable_ranges() {
+        let dump = MemoryDump { memory_space: 1, start_address: 0x0000, data: vec![0x00; 0x100], unreadable_ranges: vec![0x40..0x80] };
+        let camera_info = CameraInfo { unit_code: String::from("1020"), model_name: String::from("F90X/N90S") };
+
+        let metadata = create_metadata(&dump, &camera_info, "2024-06-21T12:00:00+02:00");
+        assert_eq!(json!("F90X/N90S"), metadata["model"]);
+        assert_eq!(json!(1), metadata["memory_space"]);
+        assert_eq!(json!(0x100), metadata["length"]);
+        assert_eq!(json!([{ "start": 0x40, "end": 0x80 }]), metadata["unreadable_ranges"]);
+
+        let parsed = parse_dump(dump.data.clone(), Some(&metadata)).unwrap();
+        assert_eq!(1, parsed.memory_space);
+        assert_eq!(dump.unreadable_ranges, parsed.unreadable_ranges);
+    }
+
+    #[test]
+    fn dump_without_metadata_should_start_from_zero() {
+        let dump = parse_dump(vec![0x00; 0x10], None).unwrap();
+        assert_eq!(0x0000..0x0010, dump.get_address_range());
+    }
+
+    #[test]
+    fn unreadable_metadata_should_be_error() {
+        let dump_path = std::env::temp_dir().join(format!("f90x-tool-dump-test-{}.bin", std::process::id()));
+        let dump_path = dump_path.to_string_lossy().to_string();
+        fs::write(&dump_path, [0x00; 0x10]).unwrap();
+        let _ = fs::remove_dir(get_metadata_path(&dump_path));
+        assert_eq!(0x10, read_dump(&dump_path).unwrap().data.len());
+
+        // A directory can't be read as the metadata file.
+        fs::create_dir(get_metadata_path(&dump_path)).unwrap();
+        assert!(read_dump(&dump_path).is_err());
+        fs::remove_dir(get_metadata_path(&dump_path)).unwrap();
+        fs::remove_file(&dump_path).unwrap();
+    }
+}
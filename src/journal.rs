@@ -0,0 +1,275 @@
+use crate::address_map::AddressMap;
+use crate::session_commands;
+use f90x_tool::camera_interface::{self, CameraInterface, SerialCameraConnection};
+use f90x_tool::camera_interface::messaging::{CameraCommand, DataPacket};
+use f90x_tool::camera_interface::models::CameraInfo;
+use f90x_tool::camera_operations;
+use f90x_tool::session::Session;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// A memory write, with the values that it replaced.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JournalEntry {
+    /// Host time of the write, in RFC 3339 format.
+    pub time: String,
+    pub device: String,
+    pub model: String,
+    pub address: u16,
+    pub original_values: Vec<u8>,
+    pub written_values: Vec<u8>,
+}
+
+/// Local file with one JSON line per memory write, so that the writes to a device can be undone.
+pub struct Journal {
+    path: PathBuf,
+    device: String,
+}
+
+impl Journal {
+    /// Opens the journal of the given device. By default the journal is in the home directory.
+    pub fn open(path: Option<&String>, serial_device: &str) -> Result<Journal> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => std::env::var_os("HOME")
+                    .map(|home| PathBuf::from(home).join(".f90x-tool-journal.jsonl"))
+                    .ok_or(anyhow!("No home directory, give the journal file with --journal"))?,
+        };
+        return Ok(Journal { path, device: serial_device.to_string() });
+    }
+
+    fn append(&self, entry: &JournalEntry) -> Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)
+                .with_context(|| format!("Could not open the journal {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        return Ok(());
+    }
+
+    fn read_entries(&self) -> Result<Vec<JournalEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let text = fs::read_to_string(&self.path)
+                .with_context(|| format!("Could not read the journal {}", self.path.display()))?;
+        return text.lines()
+                .filter(|line| !line.trim().is_empty())
+                .enumerate()
+                .map(|(index, line)| {
+                    serde_json::from_str(line)
+                            .with_context(|| format!("Invalid entry on line {} of the journal", index + 1))
+                })
+                .collect();
+    }
+
+    fn write_entries(&self, entries: &[JournalEntry]) -> Result<()> {
+        let mut text = String::new();
+        for entry in entries {
+            text += &serde_json::to_string(entry)?;
+            text += "\n";
+        }
+        return fs::write(&self.path, text)
+                .with_context(|| format!("Could not write the journal {}", self.path.display()));
+    }
+}
+
+/// A [CameraInterface] through which all the memory writes of the tool go.
+///
+/// Writes to the protected locations of the address map are refused, and so are all writes if the
+/// map is for another camera model. Before a write is sent, the
+/// values that it replaces are read and recorded in the journal.
+pub struct JournaledCamera<'a, T: CameraInterface> {
+    camera: &'a mut T,
+    journal: &'a Journal,
+    address_map: &'a AddressMap,
+    model: String,
+}
+
+impl<'a, T: CameraInterface> JournaledCamera<'a, T> {
+    pub fn new(camera: &'a mut T, journal: &'a Journal, address_map: &'a AddressMap, model: &str) -> JournaledCamera<'a, T> {
+        return JournaledCamera { camera, journal, address_map, model: model.to_string() };
+    }
+
+    fn record_write(&mut self, address: u16, values: &[u8]) -> Result<()> {
+        self.address_map.check_model(&self.model)?;
+        let range = (address as usize)..(address as usize) + values.len();
+        self.address_map.check_write(&range)?;
+        let length = u8::try_from(values.len()).map_err(|_| anyhow!("Too many values given."))?;
+        let original_values = camera_operations::read_memory(self.camera, 0, address, length)?;
+        return self.journal.append(&JournalEntry {
+            time: Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+            device: self.journal.device.clone(),
+            model: self.model.clone(),
+            address,
+            original_values,
+            written_values: values.to_vec(),
+        });
+    }
+}
+
+impl<T: CameraInterface> CameraInterface for JournaledCamera<'_, T> {
+    fn send_command(&mut self, command: &CameraCommand) -> Result<()> {
+        if let CameraCommand::WriteToMemory { address, values } = command {
+            self.record_write(*address, values)?;
+        }
+        return self.camera.send_command(command);
+    }
+
+    fn expect_ok_response(&mut self) -> Result<()> {
+        return self.camera.expect_ok_response();
+    }
+
+    fn start_new_session(&mut self) -> Result<CameraInfo> {
+        return self.camera.start_new_session();
+    }
+
+    fn upgrade_to_fast_session(&mut self) -> Result<()> {
+        return self.camera.upgrade_to_fast_session();
+    }
+
+    fn end_fast_session(&mut self) -> Result<()> {
+        return self.camera.end_fast_session();
+    }
+
+    fn expect_data_packet(&mut self, payload_length: u8) -> Result<DataPacket> {
+        return self.camera.expect_data_packet(payload_length);
+    }
+
+    fn supports_fast_session(&self) -> bool {
+        return self.camera.supports_fast_session();
+    }
+}
+
+/// Writes the values through a [JournaledCamera] of the session, and verifies them by reading
+/// back.
+pub fn write_with_journal<T: CameraInterface>(
+        session: &mut Session<T>,
+        journal: &Journal,
+        address_map: &AddressMap,
+        address: u16,
+        values: Vec<u8>) -> Result<()> {
+    let model = session.get_camera_info().ok_or(anyhow!("The session is not started"))?.model_name.clone();
+    let mut camera = JournaledCamera::new(session.get_camera()?, journal, address_map, &model);
+    return camera_operations::write_memory_verified(&mut camera, address, values);
+}
+
+/// Returns the indices of the entries to undo for the device, in the order that they are undone.
+/// Without a time, only the last write is undone.
+fn get_entries_to_undo(entries: &[JournalEntry], device: &str, since: Option<DateTime<Utc>>) -> Result<Vec<usize>> {
+    let mut indices: Vec<usize> = Vec::new();
+    for (index, entry) in entries.iter().enumerate().rev() {
+        if entry.device != device {
+            continue;
+        }
+        let Some(since) = since else {
+            indices.push(index);
+            break;
+        };
+        let time = DateTime::parse_from_rfc3339(&entry.time)
+                .with_context(|| format!("Invalid time \"{}\" in the journal", entry.time))?;
+        if time < since {
+            break;
+        }
+        indices.push(index);
+    }
+    return Ok(indices);
+}
+
+/// Restores the values from before the last write to the device, or before all the writes since
+/// the given local time. The restored writes are removed from the journal.
+pub fn undo(serial_device: &String, journal: &Journal, since: Option<&String>) -> Result<()> {
+    let since = since.map(|since| session_commands::parse_local_time(since)).transpose()?;
+    let mut entries = journal.read_entries()?;
+    let indices = get_entries_to_undo(&entries, serial_device, since)?;
+    if indices.is_empty() {
+        return Err(anyhow!("Nothing to undo for {}", serial_device));
+    }
+
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), false);
+    let model = session.start()?.model_name.clone();
+    if let Some(index) = indices.iter().find(|index| entries[**index].model != model) {
+        session.close()?;
+        return Err(anyhow!("The write at {} was done to a {}, but the camera is a {}",
+                           entries[*index].time, entries[*index].model, model));
+    }
+
+    // The indices are in reverse order, so removing one doesn't move the others.
+    for index in indices {
+        let entry = entries[index].clone();
+        let result = camera_operations::write_memory_verified(session.get_camera()?, entry.address, entry.original_values.clone());
+        if let Err(error) = result {
+            session.close()?;
+            return Err(error.context(format!("Could not undo the write at {}", entry.time)));
+        }
+        println!("Restored {:04X} from {:02X?} to {:02X?} (written at {})",
+                 entry.address, entry.written_values, entry.original_values, entry.time);
+        entries.remove(index);
+        journal.write_entries(&entries)?;
+    }
+    return session.close();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(time: &str, device: &str) -> JournalEntry {
+        return JournalEntry {
+            time: time.to_string(),
+            device: device.to_string(),
+            model: String::from("F90X/N90S"),
+            address: 0xFD40,
+            original_values: vec![0x45],
+            written_values: vec![0x5F],
+        };
+    }
+
+    fn time(value: &str) -> Option<DateTime<Utc>> {
+        return Some(DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc));
+    }
+
+    #[test]
+    fn only_the_last_write_of_the_device_should_be_undone_by_default() {
+        let entries = vec![
+            entry("2024-05-01T10:00:00.000+00:00", "/dev/ttyUSB0"),
+            entry("2024-05-01T10:01:00.000+00:00", "/dev/ttyUSB0"),
+            entry("2024-05-01T10:02:00.000+00:00", "/dev/ttyUSB1"),
+        ];
+        assert_eq!(vec![1], get_entries_to_undo(&entries, "/dev/ttyUSB0", None).unwrap());
+        assert!(get_entries_to_undo(&entries, "/dev/ttyUSB2", None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn writes_since_the_time_should_be_undone_in_reverse_order() {
+        let entries = vec![
+            entry("2024-05-01T10:00:00.000+00:00", "/dev/ttyUSB0"),
+            entry("2024-05-01T10:01:00.000+00:00", "/dev/ttyUSB0"),
+            entry("2024-05-01T10:02:00.000+00:00", "/dev/ttyUSB1"),
+            entry("2024-05-01T12:03:00.000+02:00", "/dev/ttyUSB0"),
+        ];
+        let since = time("2024-05-01T10:01:00Z");
+        assert_eq!(vec![3, 1], get_entries_to_undo(&entries, "/dev/ttyUSB0", since).unwrap());
+    }
+
+    #[test]
+    fn journal_should_keep_the_entries_in_order() {
+        let path = std::env::temp_dir().join(format!("f90x-tool-journal-test-{}.jsonl", std::process::id()));
+        let journal = Journal::open(Some(&path.to_string_lossy().to_string()), "/dev/ttyUSB0").unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(journal.read_entries().unwrap().is_empty());
+
+        let entries = vec![entry("2024-05-01T10:00:00.000+00:00", "/dev/ttyUSB0"), entry("2024-05-01T10:01:00.000+00:00", "/dev/ttyUSB0")];
+        journal.append(&entries[0]).unwrap();
+        journal.append(&entries[1]).unwrap();
+        assert_eq!(entries, journal.read_entries().unwrap());
+
+        journal.write_entries(&entries[..1]).unwrap();
+        assert_eq!(entries[..1], journal.read_entries().unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,36 @@
+//! Library for communicating with the Nikon F90x/N90s camera over the serial interface.
+//!
+//! - [camera_interface] has the serial connection and the camera session handling.
+//! - [camera_operations] has the memory access and camera control operations.
+//! - [shooting_data] has the memo holder related operations.
+//! - [session] keeps a camera session open between commands.
+//! - [camera_settings] has typed access to the camera settings, with the value types of
+//!   [settings_types]. Only scaffolding so far, as no setting location of the supported models
+//!   is known yet.
+//! - [camera_status] reads a snapshot of the camera state.
+//! - [bracketing] has the exposure bracketing sequences.
+//! - [memory_dump] reads whole memory ranges, recording the parts that can't be read.
+//! - [caching_camera] caches the memory reads, and merges nearby reads into one command.
+//! - [camera_memory] has a memory space as a file, with [std::io::Read], [std::io::Seek] and
+//!   [std::io::Write].
+//!
+//! ```no_run
+//! use f90x_tool::camera_operations;
+//!
+//! let mut camera = camera_operations::open_session(&String::from("/dev/ttyUSB0"), true)?;
+//! let values = camera_operations::read_memory(&mut camera, 0, 0xFD40, 1)?;
+//! camera_operations::end_session(&mut camera, true)?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod bracketing;
+pub mod caching_camera;
+pub mod camera_interface;
+pub mod camera_memory;
+pub mod camera_operations;
+pub mod camera_settings;
+pub mod camera_status;
+pub mod memory_dump;
+pub mod session;
+pub mod settings_types;
+pub mod shooting_data;
@@ -1,32 +1,115 @@
-mod camera_interface;
+mod address_map;
+mod backup;
 mod cli_commands;
-mod shooting_data;
+mod dump_file;
+mod journal;
+mod memory_diff;
+mod monitor;
+mod schedule;
+mod script;
+mod self_timer;
+mod session_commands;
+mod settings_profile;
+mod shell;
+mod status;
+mod sync_release;
+mod timelapse;
+mod watch;
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use f90x_tool::camera_interface::models;
+
+use anyhow::{Result, anyhow};
+use clap::{Args, Parser, Subcommand};
+use std::time::Duration;
 
 /// A tool to read a bytes at a given memory address of a Nikon F90x camera
 #[derive(Parser)]
 struct Arguments {
     #[command(subcommand)]
     command: Commands,
+    /// Address map file with more names of memory locations, in addition to the known ones.
+    #[clap(long, global = true)]
+    map: Option<String>,
+    /// Journal file that the memory writes are recorded in, for undoing them. By default
+    /// ".f90x-tool-journal.jsonl" in the home directory.
+    #[clap(long, global = true)]
+    journal: Option<String>,
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct DeviceArgs {
+    /// Serial device to use. A serial server can be given as "tcp://host:port", or as
+    /// "rfc2217://host:port" to be able to use the fast BAUD rate.
+    #[arg(short = 'd', long = "device")]
+    serial_device: Option<String>,
+    /// Use the first serial device that has a camera attached.
+    #[arg(long)]
+    auto: bool,
+}
+
+impl DeviceArgs {
+    fn get_serial_device(self) -> Result<String> {
+        match self.serial_device {
+            Some(serial_device) => Ok(serial_device),
+            None => cli_commands::find_serial_device(),
+        }
+    }
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct MultiDeviceArgs {
+    /// Serial device to use. Give more than once to release several cameras at the same time.
+    #[arg(short = 'd', long = "device")]
+    serial_devices: Vec<String>,
+    /// Use the first serial device that has a camera attached.
+    #[arg(long)]
+    auto: bool,
+}
+
+impl MultiDeviceArgs {
+    fn get_serial_devices(self) -> Result<Vec<String>> {
+        if self.auto {
+            return Ok(vec![cli_commands::find_serial_device()?]);
+        }
+        return Ok(self.serial_devices);
+    }
+}
+
+#[derive(Args)]
+#[group(required = false, multiple = false)]
+struct OptionalDeviceArgs {
+    /// Serial device to use.
+    #[arg(short = 'd', long = "device")]
+    serial_device: Option<String>,
+    /// Use the first serial device that has a camera attached.
+    #[arg(long)]
+    auto: bool,
+}
+
+impl OptionalDeviceArgs {
+    fn get_serial_device(self) -> Result<Option<String>> {
+        if self.auto {
+            return Ok(Some(cli_commands::find_serial_device()?));
+        }
+        return Ok(self.serial_device);
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Reads given memory address
     Read {
-        /// Serial device to use.
-        serial_device: String,
-        /// Address to read. Prefix with 0x for hex value.
-        #[clap(value_parser=clap_num::maybe_hex::<u16>)]
-        address: u16,
-        /// Number of bytes to read.
-        #[arg(default_value_t = 1)]
-        length: u8,
-        /// Memory space to read from.
-        #[arg(default_value_t = 0)]
-        memory_space: u8,
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Address to read, or a name from the address map like "memo.setting". Prefix with 0x for
+        /// hex value.
+        address: String,
+        /// Number of bytes to read. By default 1, or the size of the named location.
+        length: Option<u8>,
+        /// Memory space to read from. By default 0, or the space of the named location.
+        memory_space: Option<u8>,
         /// Use a 9600 BAUD rate connection instead of the default 1200.
         #[clap(short, long, action=clap::ArgAction::SetTrue)]
         fast: bool,
@@ -34,50 +117,353 @@ enum Commands {
     /// Writes to the "0" memory space starting from the given address. Number of bytes to write
     /// depends on the number of values given.
     Write {
-        /// Serial device to use.
-        serial_device: String,
-        /// Starting address to write to. Prefix with 0x for hex value.
-        #[clap(value_parser=clap_num::maybe_hex::<u16>)]
-        address: u16,
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Starting address to write to, or a name from the address map. Prefix with 0x for hex
+        /// value.
+        address: String,
         /// Byte values to write. Separate by space for multiple bytes. Prefix each with 0x for hex
-        /// value. Maximum number of bytes to be written in one go is 255.
-        #[clap(value_parser=clap_num::maybe_hex::<u8>)]
-        write_values: Vec<u8>,
+        /// value. Maximum number of bytes to be written in one go is 255. The values of a named
+        /// location can also be given by name, like "full" for "memo.setting".
+        #[clap(required = true)]
+        write_values: Vec<String>,
+        /// Only print the bytes of the write command, without writing anything.
+        #[clap(long, action=clap::ArgAction::SetTrue)]
+        dry_run: bool,
+        /// Don't ask for a confirmation when writing outside of the known safe locations.
+        #[clap(short, long, action=clap::ArgAction::SetTrue)]
+        yes: bool,
         /// Use a 9600 BAUD rate connection instead of the default 1200.
         #[clap(short, long, action=clap::ArgAction::SetTrue)]
         fast: bool,
     },
     /// Triggers auto-focus.
     Focus {
-        /// Serial device to use.
-        serial_device: String,
+        #[command(flatten)]
+        device: DeviceArgs,
     },
-    /// Releases the shutter.
+    /// Releases the shutter. With a delay or a count, a countdown is shown and Ctrl-C cancels.
+    /// With several devices, the shutters are released at the same time.
     Shoot {
-        /// Serial device to use.
-        serial_device: String,
+        #[command(flatten)]
+        devices: MultiDeviceArgs,
+        /// Time to wait before the first release, for example "10s".
+        #[clap(long, value_parser=session_commands::parse_duration, default_value = "0s")]
+        delay: Duration,
+        /// Number of frames to take.
+        #[clap(long, default_value_t = 1)]
+        count: u32,
+        /// Time between the releases when taking more than one frame.
+        #[clap(long, value_parser=session_commands::parse_duration, default_value = "1s")]
+        gap: Duration,
+        /// Trigger auto-focus this long before each release, for example "2s". Use "0s" to focus
+        /// right before the release.
+        #[clap(long, value_parser=session_commands::parse_duration)]
+        focus_before: Option<Duration>,
+    },
+    /// Releases the shutter once for each exposure compensation, restoring the original
+    /// compensation at the end. The compensation location comes from the camera profile, or from
+    /// an "exposure_compensation" location in the address map.
+    Bracket {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Exposure compensations in stops, separated by commas. For example "-1,0,+1" or
+        /// "-1/3,0,+1/3".
+        #[clap(long, required = true, value_delimiter = ',', allow_hyphen_values = true,
+               value_parser = f90x_tool::bracketing::parse_stops)]
+        stops: Vec<f64>,
+        /// Number of steps in one stop, for a location from the address map. The location holds
+        /// the compensation as a signed number of steps.
+        #[clap(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(1..))]
+        steps_per_stop: u8,
+        /// Use a 9600 BAUD rate connection instead of the default 1200.
+        #[clap(short, long, action=clap::ArgAction::SetTrue)]
+        fast: bool,
+    },
+    /// Prints a snapshot of the camera state: frame count, film, lens, exposure settings and the
+    /// memo holder. Values whose location is not mapped for the model are left out, which is
+    /// everything but the memo holder on the F90x so far.
+    Status {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Print as JSON instead of text.
+        #[clap(long, action=clap::ArgAction::SetTrue)]
+        json: bool,
+    },
+    /// Shows a full-screen dashboard of the camera status that is refreshed continuously. The "f"
+    /// key triggers auto-focus, "s" releases the shutter and "q" quits. Like with the status
+    /// command, only the memo holder is mapped on the F90x so far.
+    Monitor {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Time between the refreshes, for example "300ms".
+        #[clap(long, value_parser=session_commands::parse_duration, default_value = "300ms")]
+        interval: Duration,
+    },
+    /// Dumps a whole memory space, or a part of it, to a binary file in a fast session. The model,
+    /// memory space, date and the ranges that could not be read are written to "<out>.json".
+    Dump {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Memory space to dump.
+        #[clap(long, default_value_t = 0)]
+        space: u8,
+        /// Binary file to write.
+        #[clap(long)]
+        out: String,
+        /// First address to dump, or a name from the address map. Prefix with 0x for hex value.
+        #[clap(long, default_value = "0")]
+        start: String,
+        /// Number of bytes to dump. By default until the end of the memory space.
+        #[clap(long, value_parser=clap_num::maybe_hex::<usize>)]
+        length: Option<usize>,
+    },
+    /// Compares two dumps byte by byte, or a dump with the live camera. The changed bytes are
+    /// grouped in runs, and labeled with the names from the address map.
+    Diff {
+        /// Dump file written by the dump command.
+        old: String,
+        /// Dump file to compare with.
+        #[clap(required_unless_present = "live")]
+        new: Option<String>,
+        /// Compare with the same memory range of the camera instead of a second dump.
+        #[clap(long, action=clap::ArgAction::SetTrue, conflicts_with = "new")]
+        live: bool,
+        #[command(flatten)]
+        device: OptionalDeviceArgs,
+    },
+    /// Polls memory ranges in a fast session and prints the bytes that changed, until Ctrl-C.
+    Watch {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Address ranges to watch, like "0xFD40-0xFD47", "0xFD40+8" or "0xFD40". The addresses
+        /// can also be names from the address map, like "memo.setting".
+        #[clap(required = true)]
+        ranges: Vec<String>,
+        /// Memory space of the ranges.
+        #[clap(long, default_value_t = 0)]
+        space: u8,
+        /// Time between the polls, for example "200ms".
+        #[clap(long, value_parser=session_commands::parse_duration, default_value = "200ms")]
+        interval: Duration,
+        /// Print the changes as JSON lines instead of text.
+        #[clap(long, action=clap::ArgAction::SetTrue)]
+        json: bool,
+    },
+    /// Restores the memory values from before the last write to the device, using the journal.
+    Undo {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Undo all the writes since the given local time, like "2024-05-01 14:30".
+        #[clap(long)]
+        all_since: Option<String>,
+    },
+    /// Saves the settings locations of the address map, the ones with "backup = true", to a JSON
+    /// file.
+    Backup {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Backup file to write.
+        #[clap(long)]
+        out: String,
+    },
+    /// Writes the settings from a backup file to a camera of the same model, after showing the
+    /// differences.
+    Restore {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Backup file written by the backup command.
+        backup: String,
+        /// Don't ask for a confirmation before writing.
+        #[clap(short, long, action=clap::ArgAction::SetTrue)]
+        yes: bool,
+    },
+    /// Applies or checks a YAML settings profile, like "studio.yaml". Only the settings that are
+    /// mapped for the camera model can be used, which is only "memo_holder" on the F90x so far.
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
     },
     /// Read memory holder info.
     ReadMemoInfo {
-        /// Serial device to use.
-        serial_device: String,
-    }
+        #[command(flatten)]
+        device: DeviceArgs,
+    },
+    /// Lists the serial devices that have a camera attached.
+    Detect,
+    /// Starts an interactive shell that keeps one camera session open.
+    Shell {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Start with a 9600 BAUD rate connection instead of the default 1200.
+        #[clap(short, long, action=clap::ArgAction::SetTrue)]
+        fast: bool,
+    },
+    /// Runs the commands in a script file in one camera session. The script has one shell command
+    /// per line.
+    Run {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Script file to run.
+        script: String,
+        /// Continue with the next lines after a failing line.
+        #[clap(long, action=clap::ArgAction::SetTrue)]
+        keep_going: bool,
+        /// Use a 9600 BAUD rate connection instead of the default 1200.
+        #[clap(short, long, action=clap::ArgAction::SetTrue)]
+        fast: bool,
+    },
+    /// Releases the shutter repeatedly with a fixed interval, keeping the camera awake in between.
+    Timelapse {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Time between the releases, for example "30s" or "2m".
+        #[clap(long, value_parser=session_commands::parse_duration)]
+        interval: Duration,
+        /// Number of frames to take.
+        #[clap(long)]
+        count: u32,
+        /// Trigger auto-focus before each release.
+        #[clap(long, action=clap::ArgAction::SetTrue)]
+        focus: bool,
+        /// File to write the host time of every release to, as comma separated values.
+        #[clap(long)]
+        log: Option<String>,
+        /// Use a 9600 BAUD rate connection instead of the default 1200.
+        #[clap(short, long, action=clap::ArgAction::SetTrue)]
+        fast: bool,
+    },
+    /// Runs a schedule file in the foreground, releasing the shutter at the scheduled times. Each
+    /// line has a trigger ("cron <expression>", "at <YYYY-MM-DD HH:MM>", or "sunrise"/"sunset"
+    /// with an optional offset like "-30m") followed by "shoot" or "focus+shoot". The sun events
+    /// need a "location <latitude> <longitude>" line.
+    Schedule {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Schedule file to run.
+        schedule: String,
+        /// Use a 9600 BAUD rate connection instead of the default 1200.
+        #[clap(short, long, action=clap::ArgAction::SetTrue)]
+        fast: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileAction {
+    /// Writes the settings of the profile that differ in the camera, and verifies them. The writes
+    /// are recorded in the journal, so that they can be undone.
+    Apply {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Profile file. A name without an extension, like "studio", is read from "studio.yaml".
+        profile: String,
+    },
+    /// Prints the settings of the camera that differ from the profile.
+    Check {
+        #[command(flatten)]
+        device: DeviceArgs,
+        /// Profile file. A name without an extension, like "studio", is read from "studio.yaml".
+        profile: String,
+    },
 }
 
 fn main() -> Result<()> {
     env_logger::init();
     let arguments = Arguments::parse();
+    // The map is only loaded by the commands that use it, so that a broken map file doesn't break
+    // the others. The names are resolved before connecting, and the F90x is the only supported
+    // model so far, so its known locations are used. The writes check that the connected camera
+    // is the same model.
+    let load_address_map = || address_map::AddressMap::load_with_defaults(arguments.map.as_ref(), &models::F90X_PROFILE);
 
     match arguments.command {
-        Commands::Read { serial_device, address, length, memory_space, fast } => {
-            cli_commands::read_memory_in_new_session(&serial_device, address, length, memory_space, fast)
+        Commands::Read { device, address, length, memory_space, fast } => {
+            let serial_device = device.get_serial_device()?;
+            cli_commands::read_memory_in_new_session(&serial_device, &load_address_map()?, &address, length, memory_space, fast)
         }?,
-        Commands::Write { serial_device, address, write_values, fast } => {
-            cli_commands::write_memory_in_new_session(&serial_device, address, write_values, fast)?
+        Commands::Write { device, address, write_values, dry_run, yes, fast } => {
+            let options = cli_commands::WriteOptions { dry_run, assume_yes: yes, use_fast_session: fast };
+            let serial_device = device.get_serial_device()?;
+            let journal = journal::Journal::open(arguments.journal.as_ref(), &serial_device)?;
+            cli_commands::write_memory_in_new_session(&serial_device, &load_address_map()?, &journal, &address, &write_values, &options)?
+        },
+        Commands::Focus { device } => cli_commands::autofocus_in_new_session(&device.get_serial_device()?)?,
+        Commands::Shoot { devices, delay, count, gap, focus_before } => {
+            let options = self_timer::SelfTimerOptions { delay, count, gap, focus_before };
+            match devices.get_serial_devices()?.as_slice() {
+                [serial_device] => self_timer::release_with_self_timer(serial_device, &options)?,
+                serial_devices if options.is_single_release() => sync_release::release_synchronized(serial_devices)?,
+                _ => return Err(anyhow!("The self-timer options can only be used with one device")),
+            }
+        },
+        Commands::Bracket { device, stops, steps_per_stop, fast } => {
+            let serial_device = device.get_serial_device()?;
+            let journal = journal::Journal::open(arguments.journal.as_ref(), &serial_device)?;
+            cli_commands::shoot_bracket_in_new_session(&serial_device, &load_address_map()?, &journal, &stops, steps_per_stop, fast)?
+        },
+        Commands::Status { device, json } => status::print_status_in_new_session(&device.get_serial_device()?, json)?,
+        Commands::Monitor { device, interval } => monitor::run_monitor(&device.get_serial_device()?, interval)?,
+        Commands::Dump { device, space, out, start, length } => {
+            let start = load_address_map()?.resolve_in_space(&start, space)?.address;
+            let length = length.unwrap_or(0x10000 - (start as usize));
+            dump_file::dump_to_file(&device.get_serial_device()?, space, start, length, &out)?
+        },
+        Commands::Diff { old, new, live, device } => {
+            let serial_device = match live {
+                true => Some(device.get_serial_device()?.ok_or(anyhow!("--live needs --device or --auto"))?),
+                false => None,
+            };
+            memory_diff::print_diff(&old, new.as_ref(), serial_device.as_ref(), &load_address_map()?)?
+        },
+        Commands::Watch { device, ranges, space, interval, json } => {
+            watch::watch_memory(&device.get_serial_device()?, space, &ranges, interval, &load_address_map()?, json)?
+        },
+        Commands::ReadMemoInfo { device } => {
+            cli_commands::read_and_print_memo_holder_info_in_new_session(&device.get_serial_device()?)?
+        },
+        Commands::Detect => cli_commands::detect_and_print_cameras()?,
+        Commands::Undo { device, all_since } => {
+            let serial_device = device.get_serial_device()?;
+            let journal = journal::Journal::open(arguments.journal.as_ref(), &serial_device)?;
+            journal::undo(&serial_device, &journal, all_since.as_ref())?
+        },
+        Commands::Backup { device, out } => backup::backup_to_file(&device.get_serial_device()?, &load_address_map()?, &out)?,
+        Commands::Restore { device, backup, yes } => {
+            let serial_device = device.get_serial_device()?;
+            let journal = journal::Journal::open(arguments.journal.as_ref(), &serial_device)?;
+            backup::restore_from_file(&serial_device, &load_address_map()?, &journal, &backup, yes)?
+        },
+        Commands::Profile { action: ProfileAction::Apply { device, profile } } => {
+            let serial_device = device.get_serial_device()?;
+            let journal = journal::Journal::open(arguments.journal.as_ref(), &serial_device)?;
+            settings_profile::apply_profile(&serial_device, &load_address_map()?, &journal, &profile)?
+        },
+        Commands::Profile { action: ProfileAction::Check { device, profile } } => {
+            settings_profile::check_profile(&device.get_serial_device()?, &profile)?
+        },
+        Commands::Shell { device, fast } => {
+            let serial_device = device.get_serial_device()?;
+            let journal = journal::Journal::open(arguments.journal.as_ref(), &serial_device)?;
+            shell::run_shell(&serial_device, &load_address_map()?, &journal, fast)?
+        },
+        Commands::Run { device, script, keep_going, fast } => {
+            let serial_device = device.get_serial_device()?;
+            let journal = journal::Journal::open(arguments.journal.as_ref(), &serial_device)?;
+            script::run_script(&serial_device, &load_address_map()?, &journal, &script, keep_going, fast)?
+        },
+        Commands::Timelapse { device, interval, count, focus, log, fast } => {
+            let options = timelapse::TimelapseOptions {
+                interval,
+                count,
+                focus_before_release: focus,
+                log_file: log,
+                use_fast_session: fast,
+            };
+            timelapse::run_timelapse(&device.get_serial_device()?, &options)?
+        },
+        Commands::Schedule { device, schedule, fast } => {
+            schedule::run_schedule(&device.get_serial_device()?, &schedule, fast)?
         },
-        Commands::Focus { serial_device } => cli_commands::autofocus_in_new_session(&serial_device)?,
-        Commands::Shoot { serial_device } => cli_commands::release_shutter_in_new_session(&serial_device)?,
-        Commands::ReadMemoInfo { serial_device } => cli_commands::read_and_print_memo_holder_info_in_new_session(&serial_device)?,
     };
 
     return Ok(());
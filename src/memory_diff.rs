@@ -0,0 +1,81 @@
+use crate::address_map::AddressMap;
+use crate::dump_file;
+use f90x_tool::camera_interface::{self, SerialCameraConnection};
+use f90x_tool::memory_dump::{self, ChangedRun, MemoryDump};
+use f90x_tool::session::Session;
+
+use anyhow::{Result, anyhow};
+
+/// Formats bytes as space separated hex values.
+pub(crate) fn format_values(values: &[u8]) -> String {
+    return values.iter().map(|value| format!("{:02X}", value)).collect::<Vec<String>>().join(" ");
+}
+
+/// Formats a changed run with its address range, the names of the known locations in it, and
+/// the old and new values.
+pub fn format_changed_run(run: &ChangedRun, memory_space: u8, address_map: &AddressMap) -> String {
+    let range = run.start_address..run.start_address + run.old_values.len();
+    let mut header = match range.len() {
+        1 => format!("{:04X} (1 byte)", range.start),
+        length => format!("{:04X}-{:04X} ({} bytes)", range.start, range.end - 1, length),
+    };
+    let names: Vec<&str> = address_map.find_overlapping(memory_space, &range).iter().map(|entry| entry.name.as_str()).collect();
+    if !names.is_empty() {
+        header += &format!(" {}", names.join(", "));
+    }
+
+    let mut text = header + "\n";
+    for (old_values, new_values) in run.old_values.chunks(16).zip(run.new_values.chunks(16)) {
+        text += &format!("  - {}\n  + {}\n", format_values(old_values), format_values(new_values));
+    }
+    return text;
+}
+
+fn read_live_dump(serial_device: &String, reference: &MemoryDump) -> Result<MemoryDump> {
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), true);
+    session.start()?;
+    let dump = memory_dump::dump_memory(&mut session, reference.memory_space, reference.start_address, reference.data.len(), |_| ());
+    session.close()?;
+    return dump;
+}
+
+/// Compares a dump with another dump, or with the same memory range of the camera if no other
+/// dump is given, and prints the changed runs.
+pub fn print_diff(old_dump_file: &String, new_dump_file: Option<&String>, serial_device: Option<&String>, address_map: &AddressMap) -> Result<()> {
+    let old = dump_file::read_dump(old_dump_file)?;
+    let new = match (new_dump_file, serial_device) {
+        (Some(new_dump_file), _) => dump_file::read_dump(new_dump_file)?,
+        (None, Some(serial_device)) => read_live_dump(serial_device, &old)?,
+        (None, None) => return Err(anyhow!("Give a second dump, or --live with a device")),
+    };
+
+    let runs = memory_dump::diff_dumps(&old, &new)?;
+    for run in &runs {
+        print!("{}", format_changed_run(run, old.memory_space, address_map));
+    }
+    let changed_bytes: usize = runs.iter().map(|run| run.old_values.len()).sum();
+    println!("{} changed run(s), {} byte(s)", runs.len(), changed_bytes);
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_run_should_be_labeled_with_known_names() {
+        let address_map = AddressMap::parse("[[address]]\nname = \"memo.setting\"\naddress = 0xFD40\n").unwrap();
+        let run = ChangedRun { start_address: 0xFD40, old_values: vec![0x45], new_values: vec![0x5F] };
+        assert_eq!("FD40 (1 byte) memo.setting\n  - 45\n  + 5F\n", format_changed_run(&run, 0, &address_map));
+        assert_eq!("FD40 (1 byte)\n  - 45\n  + 5F\n", format_changed_run(&run, 1, &address_map));
+    }
+
+    #[test]
+    fn long_run_should_be_split_in_lines() {
+        let run = ChangedRun { start_address: 0x1000, old_values: vec![0x00; 18], new_values: vec![0x01; 18] };
+        let text = format_changed_run(&run, 0, &AddressMap::default());
+        assert!(text.starts_with("1000-1011 (18 bytes)\n  - 00 00"));
+        assert_eq!(5, text.lines().count());
+    }
+}
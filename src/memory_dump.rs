@@ -0,0 +1,256 @@
+use crate::camera_interface::CameraInterface;
+use crate::camera_operations::{self, MAX_READ_LENGTH};
+use crate::session::Session;
+
+use anyhow::{Result, anyhow};
+use log::debug;
+use std::ops::Range;
+
+/// Contents of a memory range. The bytes that could not be read are zero.
+#[derive(Debug)]
+pub struct MemoryDump {
+    pub memory_space: u8,
+    pub start_address: u16,
+    pub data: Vec<u8>,
+    /// Address ranges that could not be read.
+    pub unreadable_ranges: Vec<Range<usize>>,
+}
+
+impl MemoryDump {
+    /// Returns the address range of the dump.
+    pub fn get_address_range(&self) -> Range<usize> {
+        return (self.start_address as usize)..(self.start_address as usize) + self.data.len();
+    }
+
+    fn is_readable(&self, address: usize) -> bool {
+        return !self.unreadable_ranges.iter().any(|range| range.contains(&address));
+    }
+}
+
+/// Consecutive bytes that differ between two dumps.
+#[derive(Debug, PartialEq)]
+pub struct ChangedRun {
+    pub start_address: usize,
+    pub old_values: Vec<u8>,
+    pub new_values: Vec<u8>,
+}
+
+/// Compares the addresses that are in both dumps, byte by byte. The bytes that could not be read
+/// in either dump are skipped.
+pub fn diff_dumps(old: &MemoryDump, new: &MemoryDump) -> Result<Vec<ChangedRun>> {
+    if old.memory_space != new.memory_space {
+        return Err(anyhow!("The dumps are from different memory spaces: {} and {}", old.memory_space, new.memory_space));
+    }
+    let old_range = old.get_address_range();
+    let new_range = new.get_address_range();
+    let common_range = old_range.start.max(new_range.start)..old_range.end.min(new_range.end);
+    if common_range.is_empty() {
+        return Err(anyhow!("The dumps have no addresses in common"));
+    }
+
+    let mut runs: Vec<ChangedRun> = Vec::new();
+    for address in common_range {
+        let old_value = old.data[address - old_range.start];
+        let new_value = new.data[address - new_range.start];
+        if old_value == new_value || !old.is_readable(address) || !new.is_readable(address) {
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run) if run.start_address + run.old_values.len() == address => {
+                run.old_values.push(old_value);
+                run.new_values.push(new_value);
+            },
+            _ => runs.push(ChangedRun { start_address: address, old_values: vec![old_value], new_values: vec![new_value] }),
+        }
+    }
+    return Ok(runs);
+}
+
+/// Number of times that a session that could not be started again is tried to start before a
+/// dump is given up.
+const MAX_START_ATTEMPTS: u32 = 3;
+
+/// Starts the session if an earlier start failed. Fails if the camera can't be reached.
+fn ensure_started<T: CameraInterface>(session: &mut Session<T>) -> Result<()> {
+    let mut attempt = 1;
+    while session.get_camera_info().is_none() {
+        match session.start() {
+            Ok(_) => (),
+            Err(error) if attempt >= MAX_START_ATTEMPTS => {
+                return Err(error.context(format!("The camera could not be reached after {} attempts", attempt)));
+            },
+            Err(error) => debug!("Could not start the session: {:#}", error),
+        }
+        attempt += 1;
+    }
+    return Ok(());
+}
+
+/// Starts the session again, as the camera might be left in the middle of a command. A failed
+/// start is tried again by [ensure_started] before the next chunk.
+fn restart_after_error<T: CameraInterface>(session: &mut Session<T>) -> bool {
+    if let Err(error) = session.start() {
+        debug!("Could not start the session again: {:#}", error);
+        return false;
+    }
+    return true;
+}
+
+fn read_chunk<T: CameraInterface>(session: &mut Session<T>, memory_space: u8, address: u16, length: u8) -> Result<Vec<u8>> {
+    let result = camera_operations::read_memory(session.get_camera()?, memory_space, address, length);
+    let Err(error) = result else {
+        return result;
+    };
+    debug!("Could not read {:04X}+{:X}, trying again: {:#}", address, length, error);
+    if !restart_after_error(session) {
+        return Err(error);
+    }
+    let result = camera_operations::read_memory(session.get_camera()?, memory_space, address, length);
+    if result.is_err() {
+        restart_after_error(session);
+    }
+    return result;
+}
+
+/// Reads a memory range in chunks, recording the chunks that can't be read instead of stopping.
+///
+/// A failed chunk is tried once more after starting the session again. If the session can't be
+/// started again in [MAX_START_ATTEMPTS] attempts, the camera is given up and the dump fails.
+/// `on_progress` is called with the number of bytes done after each chunk.
+pub fn dump_memory<T: CameraInterface>(
+        session: &mut Session<T>,
+        memory_space: u8,
+        start_address: u16,
+        length: usize,
+        mut on_progress: impl FnMut(usize)) -> Result<MemoryDump> {
+    if (start_address as usize) + length > 0x10000 {
+        return Err(anyhow!("Memory range {:04X}+{:X} is out of the address space", start_address, length));
+    }
+    let mut dump = MemoryDump { memory_space, start_address, data: Vec::with_capacity(length), unreadable_ranges: Vec::new() };
+    while dump.data.len() < length {
+        let address = (start_address as usize) + dump.data.len();
+        let chunk_length = (length - dump.data.len()).min(MAX_READ_LENGTH as usize);
+        ensure_started(session)?;
+        match read_chunk(session, memory_space, address as u16, chunk_length as u8) {
+            Ok(mut values) => dump.data.append(&mut values),
+            Err(_) => {
+                dump.data.resize(dump.data.len() + chunk_length, 0x00);
+                let range = address..(address + chunk_length);
+                match dump.unreadable_ranges.last_mut() {
+                    Some(last) if last.end == range.start => last.end = range.end,
+                    _ => dump.unreadable_ranges.push(range),
+                }
+            },
+        }
+        on_progress(dump.data.len());
+    }
+    return Ok(dump);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_interface::MockCameraInterface;
+    use crate::camera_interface::fake::FakeCamera;
+    use crate::camera_interface::messaging::DataPacket;
+    use crate::camera_interface::models::CameraInfo;
+
+    /// Returns a camera whose session starts and data packets follow the given results, in order.
+    fn camera_with_results(start_results: Vec<bool>, read_results: Vec<bool>) -> MockCameraInterface {
+        let mut mock_camera = MockCameraInterface::new();
+        let mut start_results = start_results.into_iter();
+        mock_camera.expect_start_new_session()
+                   .times(start_results.len())
+                   .returning(move || match start_results.next().unwrap() {
+                       true => Ok(CameraInfo { unit_code: String::from("1020"), model_name: String::from("F90X/N90S") }),
+                       false => Err(anyhow!("Timeout")),
+                   });
+        mock_camera.expect_send_command().returning(|_| Ok(()));
+        let mut read_results = read_results.into_iter();
+        mock_camera.expect_expect_data_packet()
+                   .times(read_results.len())
+                   .returning(move |length| match read_results.next().unwrap() {
+                       true => Ok(DataPacket { bytes: vec![0xAA; length as usize] }),
+                       false => Err(anyhow!("Timeout")),
+                   });
+        return mock_camera;
+    }
+
+    #[test]
+    fn unreadable_chunks_should_be_recorded_and_merged() {
+        let mut fake_camera = FakeCamera::with_address_pattern();
+        fake_camera.unreadable_addresses = 0x1040..0x10C0;
+        let mut session = Session::new(fake_camera, false);
+        session.start().unwrap();
+
+        let mut progress: Vec<usize> = Vec::new();
+        let dump = dump_memory(&mut session, 0, 0x1000, 0x110, |done| progress.push(done)).unwrap();
+        assert_eq!(0x110, dump.data.len());
+        assert_eq!(vec![0x1040..0x10C0], dump.unreadable_ranges);
+        assert_eq!(0x3F, dump.data[0x3F]);
+        assert_eq!(0x00, dump.data[0x41]);
+        assert_eq!(0x0F, dump.data[0x10F]);
+        assert_eq!(vec![0x40, 0x80, 0xC0, 0x100, 0x110], progress);
+    }
+
+    #[test]
+    fn failed_start_should_be_tried_again_before_the_next_chunk() {
+        // The first chunk fails and so does the start after it, then the camera is back.
+        let mut session = Session::new(camera_with_results(vec![true, false, true], vec![false, true]), false);
+        session.start().unwrap();
+
+        let dump = dump_memory(&mut session, 0, 0x1000, 0x80, |_| ()).unwrap();
+        assert_eq!(vec![0x1000..0x1040], dump.unreadable_ranges);
+        assert_eq!(0xAA, dump.data[0x40]);
+    }
+
+    #[test]
+    fn unreachable_camera_should_fail_the_dump() {
+        let start_results = vec![true, false, false, false, false];
+        let mut session = Session::new(camera_with_results(start_results, vec![false]), false);
+        session.start().unwrap();
+
+        let error = dump_memory(&mut session, 0, 0x1000, 0x80, |_| ()).unwrap_err();
+        assert_eq!("The camera could not be reached after 3 attempts", error.to_string());
+    }
+
+    fn test_dump(start_address: u16, data: Vec<u8>) -> MemoryDump {
+        return MemoryDump { memory_space: 0, start_address, data, unreadable_ranges: Vec::new() };
+    }
+
+    #[test]
+    fn changed_bytes_should_be_grouped_in_runs() {
+        let old = test_dump(0xFD40, vec![0x45, 0x00, 0x10, 0x11, 0x12, 0x13]);
+        let new = test_dump(0xFD40, vec![0x5F, 0x00, 0x20, 0x21, 0x12, 0x13]);
+
+        let runs = diff_dumps(&old, &new).unwrap();
+        assert_eq!(vec![
+            ChangedRun { start_address: 0xFD40, old_values: vec![0x45], new_values: vec![0x5F] },
+            ChangedRun { start_address: 0xFD42, old_values: vec![0x10, 0x11], new_values: vec![0x20, 0x21] },
+        ], runs);
+    }
+
+    #[test]
+    fn only_common_readable_addresses_should_be_compared() {
+        let old = test_dump(0x1000, vec![0x01, 0x02, 0x03, 0x04]);
+        let mut new = test_dump(0x1002, vec![0xFF, 0xFF, 0xFF]);
+        new.unreadable_ranges.push(0x1003..0x1005);
+
+        let runs = diff_dumps(&old, &new).unwrap();
+        assert_eq!(vec![ChangedRun { start_address: 0x1002, old_values: vec![0x03], new_values: vec![0xFF] }], runs);
+    }
+
+    #[test]
+    fn dumps_without_common_addresses_should_be_error() {
+        assert!(diff_dumps(&test_dump(0x1000, vec![0x00; 2]), &test_dump(0x1002, vec![0x00; 2])).is_err());
+        let mut other_space = test_dump(0x1000, vec![0x00; 2]);
+        other_space.memory_space = 1;
+        assert!(diff_dumps(&test_dump(0x1000, vec![0x00; 2]), &other_space).is_err());
+    }
+
+    #[test]
+    fn dump_outside_the_address_space_should_be_error() {
+        let mut session = Session::new(MockCameraInterface::new(), false);
+        assert!(dump_memory(&mut session, 0, 0xFFFF, 2, |_| ()).is_err());
+    }
+}
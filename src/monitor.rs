@@ -0,0 +1,231 @@
+use crate::status;
+use f90x_tool::camera_interface::{self, CameraInterface, SerialCameraConnection};
+use f90x_tool::camera_interface::models::CameraProfile;
+use f90x_tool::camera_operations;
+use f90x_tool::camera_status;
+use f90x_tool::session::Session;
+
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use std::io::{Stdout, Write};
+use std::time::{Duration, Instant};
+
+/// How long a changed value stays highlighted.
+const HIGHLIGHT_TIME: Duration = Duration::from_secs(2);
+
+/// A line of the dashboard, with the time it last changed.
+struct DashboardLine {
+    text: String,
+    changed_at: Option<Instant>,
+}
+
+/// Replaces the lines with the new ones, marking the ones that differ as changed.
+fn update_lines(lines: &mut Vec<DashboardLine>, new_lines: Vec<String>, now: Instant) {
+    let is_first_update = lines.is_empty();
+    lines.resize_with(new_lines.len(), || DashboardLine { text: String::new(), changed_at: None });
+    for (line, new_text) in lines.iter_mut().zip(new_lines) {
+        if line.text != new_text {
+            line.text = new_text;
+            line.changed_at = if is_first_update { None } else { Some(now) };
+        }
+    }
+}
+
+fn is_highlighted(line: &DashboardLine, now: Instant) -> bool {
+    return line.changed_at.is_some_and(|changed_at| now.duration_since(changed_at) < HIGHLIGHT_TIME);
+}
+
+/// Messages below the dashboard. The result of a key stays until the next key, and a refresh
+/// error until the next successful refresh.
+#[derive(Default)]
+struct Messages {
+    action_result: String,
+    refresh_error: String,
+}
+
+impl Messages {
+    fn get_lines(&self) -> Vec<&str> {
+        return [self.action_result.as_str(), self.refresh_error.as_str()].into_iter()
+                .filter(|message| !message.is_empty())
+                .collect();
+    }
+}
+
+/// Puts the terminal in full-screen raw mode, and restores it when dropped.
+struct FullScreen {
+    stdout: Stdout,
+}
+
+impl FullScreen {
+    fn enter() -> Result<FullScreen> {
+        let mut stdout = std::io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+        return Ok(FullScreen { stdout });
+    }
+
+    fn draw(&mut self, lines: &[DashboardLine], messages: &Messages) -> Result<()> {
+        let now = Instant::now();
+        queue!(self.stdout, cursor::MoveTo(0, 0), Clear(ClearType::All))?;
+        queue!(self.stdout, SetAttribute(Attribute::Bold), Print("f: focus, s: shoot, q: quit"),
+               SetAttribute(Attribute::Reset))?;
+        for (index, line) in lines.iter().enumerate() {
+            queue!(self.stdout, cursor::MoveTo(0, (index + 2) as u16))?;
+            if is_highlighted(line, now) {
+                queue!(self.stdout, SetAttribute(Attribute::Reverse), Print(&line.text), SetAttribute(Attribute::Reset))?;
+            } else {
+                queue!(self.stdout, Print(&line.text))?;
+            }
+        }
+        for (index, message) in messages.get_lines().iter().enumerate() {
+            queue!(self.stdout, cursor::MoveTo(0, (lines.len() + 3 + index) as u16), Print(message))?;
+        }
+        self.stdout.flush()?;
+        return Ok(());
+    }
+}
+
+impl Drop for FullScreen {
+    fn drop(&mut self) {
+        let _ = execute!(self.stdout, cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+enum KeyAction {
+    Focus,
+    Shoot,
+    Quit,
+}
+
+fn get_key_action(key: &KeyEvent) -> Option<KeyAction> {
+    if key.kind != KeyEventKind::Press {
+        return None;
+    }
+    return match key.code {
+        KeyCode::Char('f') => Some(KeyAction::Focus),
+        KeyCode::Char('s') | KeyCode::Char(' ') => Some(KeyAction::Shoot),
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Some(KeyAction::Quit),
+        KeyCode::Char('q') | KeyCode::Esc => Some(KeyAction::Quit),
+        _ => None,
+    };
+}
+
+fn run_action<T: CameraInterface>(session: &mut Session<T>, action: &KeyAction) -> Result<&'static str> {
+    match action {
+        KeyAction::Focus => {
+            camera_operations::autofocus(session.get_camera()?)?;
+            return Ok("Focused.");
+        },
+        KeyAction::Shoot => {
+            camera_operations::release_shutter(session.get_camera()?)?;
+            return Ok("Released.");
+        },
+        KeyAction::Quit => return Ok(""),
+    }
+}
+
+/// Shows a full-screen dashboard of the camera status, refreshed with the given interval. The
+/// keys trigger auto-focus and release the shutter.
+pub fn run_monitor(serial_device: &String, refresh_interval: Duration) -> Result<()> {
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), true);
+    let profile = session.start()?.get_profile()?;
+
+    let result = run_dashboard(&mut session, profile, refresh_interval);
+    session.close()?;
+    return result;
+}
+
+fn run_dashboard<T: CameraInterface>(
+        session: &mut Session<T>,
+        profile: &CameraProfile,
+        refresh_interval: Duration) -> Result<()> {
+    let mut screen = FullScreen::enter()?;
+    let mut lines: Vec<DashboardLine> = Vec::new();
+    let mut messages = Messages::default();
+    loop {
+        let next_refresh = Instant::now() + refresh_interval;
+        match session.get_camera().and_then(|camera| camera_status::get_camera_status(camera, profile)) {
+            Ok(camera_status) => {
+                let new_lines = status::format_status_text(&camera_status).lines().map(String::from).collect();
+                update_lines(&mut lines, new_lines, Instant::now());
+                messages.refresh_error.clear();
+            },
+            Err(error) => {
+                messages.refresh_error = format!("Could not read the status: {:#}", error);
+                // The camera might be left in the middle of a command.
+                let _ = session.start();
+            },
+        }
+        screen.draw(&lines, &messages)?;
+
+        while let Some(timeout) = next_refresh.checked_duration_since(Instant::now()) {
+            if !event::poll(timeout)? {
+                break;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            let Some(action) = get_key_action(&key) else {
+                continue;
+            };
+            if let KeyAction::Quit = action {
+                return Ok(());
+            }
+            messages.action_result = match run_action(session, &action) {
+                Ok(result) => result.to_string(),
+                Err(error) => format!("Failed: {:#}", error),
+            };
+            screen.draw(&lines, &messages)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(lines: &[DashboardLine]) -> Vec<&str> {
+        return lines.iter().map(|line| line.text.as_str()).collect();
+    }
+
+    #[test]
+    fn changed_lines_should_be_highlighted() {
+        let start = Instant::now();
+        let mut lines: Vec<DashboardLine> = Vec::new();
+        update_lines(&mut lines, vec![String::from("Frame count: 1"), String::from("Film loaded: true")], start);
+        assert!(!is_highlighted(&lines[0], start));
+
+        let now = start + Duration::from_millis(300);
+        update_lines(&mut lines, vec![String::from("Frame count: 2"), String::from("Film loaded: true")], now);
+        assert_eq!(vec!["Frame count: 2", "Film loaded: true"], texts(&lines));
+        assert!(is_highlighted(&lines[0], now));
+        assert!(!is_highlighted(&lines[1], now));
+        assert!(!is_highlighted(&lines[0], now + HIGHLIGHT_TIME));
+    }
+
+    #[test]
+    fn action_result_should_stay_after_the_refresh_error_is_cleared() {
+        let mut messages = Messages {
+            action_result: String::from("Failed: Timeout"),
+            refresh_error: String::from("Could not read the status: Timeout"),
+        };
+        assert_eq!(vec!["Failed: Timeout", "Could not read the status: Timeout"], messages.get_lines());
+
+        messages.refresh_error.clear();
+        assert_eq!(vec!["Failed: Timeout"], messages.get_lines());
+    }
+
+    #[test]
+    fn keys_should_trigger_actions() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        assert!(matches!(get_key_action(&key(KeyCode::Char('f'))), Some(KeyAction::Focus)));
+        assert!(matches!(get_key_action(&key(KeyCode::Char(' '))), Some(KeyAction::Shoot)));
+        assert!(matches!(get_key_action(&key(KeyCode::Esc)), Some(KeyAction::Quit)));
+        assert!(get_key_action(&key(KeyCode::Char('x'))).is_none());
+    }
+}
@@ -0,0 +1,336 @@
+use crate::session_commands;
+use crate::timelapse;
+use f90x_tool::camera_interface::{self, CameraInterface, SerialCameraConnection};
+use f90x_tool::session::Session;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat, TimeDelta, Utc};
+use std::fs;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+use sunrise::{Coordinates, SolarDay, SolarEvent};
+
+/// What to do at a scheduled time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Shoot,
+    FocusAndShoot,
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Action> {
+        return match value {
+            "shoot" => Ok(Action::Shoot),
+            "focus+shoot" => Ok(Action::FocusAndShoot),
+            _ => Err(anyhow!("Unknown action \"{}\". Use shoot or focus+shoot.", value)),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+enum Trigger {
+    Cron(Box<cron::Schedule>),
+    At(DateTime<Utc>),
+    Sun { event: SunEvent, offset: TimeDelta },
+}
+
+struct ScheduleEntry {
+    trigger: Trigger,
+    action: Action,
+    line_number: usize,
+}
+
+/// A scheduled action, with the line of the schedule file that it comes from.
+#[derive(Debug, PartialEq)]
+pub struct ScheduledAction {
+    pub time: DateTime<Local>,
+    pub action: Action,
+    pub line_number: usize,
+}
+
+/// A shooting plan, read from a schedule file. Each line has a trigger followed by an action:
+///
+/// ```text
+/// location 52.37 4.89                 # Latitude and longitude for the sun events
+/// cron */10 6-18 * * * shoot          # Cron expression, in local time
+/// at 2024-06-21 04:30 focus+shoot     # Absolute local time
+/// sunrise -30m shoot                  # Offset from the sunrise or the sunset
+/// sunset +15m shoot
+/// ```
+pub struct Schedule {
+    location: Option<Coordinates>,
+    entries: Vec<ScheduleEntry>,
+}
+
+/// Parses an offset like "-30m" or "+1h".
+fn parse_offset(value: &str) -> Result<TimeDelta> {
+    let (is_negative, duration) = match value.strip_prefix('-') {
+        Some(duration) => (true, duration),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let offset = TimeDelta::from_std(session_commands::parse_duration(duration)?)?;
+    return Ok(if is_negative { -offset } else { offset });
+}
+
+fn parse_cron_expression(fields: &[&str]) -> Result<cron::Schedule> {
+    let expression = match fields.len() {
+        // The usual cron format has no seconds field.
+        5 => format!("0 {}", fields.join(" ")),
+        6 | 7 => fields.join(" "),
+        _ => return Err(anyhow!("A cron expression should have 5, 6 or 7 fields")),
+    };
+    return cron::Schedule::from_str(&expression)
+            .map_err(|error| anyhow!("Invalid cron expression \"{}\": {}", fields.join(" "), error));
+}
+
+fn get_sun_event_time(location: Coordinates, date: NaiveDate, event: SunEvent) -> DateTime<Utc> {
+    let event = match event {
+        SunEvent::Sunrise => SolarEvent::Sunrise,
+        SunEvent::Sunset => SolarEvent::Sunset,
+    };
+    return SolarDay::new(location, date).event_time(event);
+}
+
+impl Schedule {
+    /// Parses the contents of a schedule file. Everything after a "#" is a comment.
+    pub fn parse(text: &str) -> Result<Schedule> {
+        let mut location: Option<Coordinates> = None;
+        let mut entries: Vec<ScheduleEntry> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or_default();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some((&keyword, arguments)) = words.split_first() else {
+                continue;
+            };
+            let result = if keyword == "location" {
+                Schedule::parse_location(arguments).map(|coordinates| location = Some(coordinates))
+            } else {
+                Schedule::parse_entry(keyword, arguments, line_number).map(|entry| entries.push(entry))
+            };
+            result.with_context(|| format!("Line {}", line_number))?;
+        }
+
+        let has_sun_events = entries.iter().any(|entry| matches!(entry.trigger, Trigger::Sun { .. }));
+        if has_sun_events && location.is_none() {
+            return Err(anyhow!("The sunrise and sunset times need a \"location <latitude> <longitude>\" line"));
+        }
+        return Ok(Schedule { location, entries });
+    }
+
+    fn parse_location(arguments: &[&str]) -> Result<Coordinates> {
+        let [latitude, longitude] = arguments else {
+            return Err(anyhow!("Usage: location <latitude> <longitude>"));
+        };
+        let latitude: f64 = latitude.parse().map_err(|_| anyhow!("Invalid latitude \"{}\"", latitude))?;
+        let longitude: f64 = longitude.parse().map_err(|_| anyhow!("Invalid longitude \"{}\"", longitude))?;
+        return Coordinates::new(latitude, longitude).ok_or(anyhow!("The coordinates are out of range"));
+    }
+
+    fn parse_entry(keyword: &str, arguments: &[&str], line_number: usize) -> Result<ScheduleEntry> {
+        let Some((action, trigger_arguments)) = arguments.split_last() else {
+            return Err(anyhow!("Missing the action after \"{}\"", keyword));
+        };
+        let action: Action = action.parse()?;
+        let trigger = match (keyword, trigger_arguments) {
+            ("cron", fields) => Trigger::Cron(Box::new(parse_cron_expression(fields)?)),
+            ("at", time) => Trigger::At(session_commands::parse_local_time(&time.join(" "))?),
+            ("sunrise", []) => Trigger::Sun { event: SunEvent::Sunrise, offset: TimeDelta::zero() },
+            ("sunrise", [offset]) => Trigger::Sun { event: SunEvent::Sunrise, offset: parse_offset(offset)? },
+            ("sunset", []) => Trigger::Sun { event: SunEvent::Sunset, offset: TimeDelta::zero() },
+            ("sunset", [offset]) => Trigger::Sun { event: SunEvent::Sunset, offset: parse_offset(offset)? },
+            ("sunrise" | "sunset", _) => return Err(anyhow!("Usage: {} [offset] <action>", keyword)),
+            _ => return Err(anyhow!("Unknown trigger \"{}\". Use cron, at, sunrise or sunset.", keyword)),
+        };
+        return Ok(ScheduleEntry { trigger, action, line_number });
+    }
+
+    /// Returns the first time of the trigger after the given time, or at it if `include_after` is set.
+    fn get_next_time(&self, trigger: &Trigger, after: &DateTime<Local>, include_after: bool) -> Option<DateTime<Utc>> {
+        let is_next = |time: &DateTime<Utc>| time > after || (include_after && time == after);
+        match trigger {
+            Trigger::Cron(schedule) if include_after && schedule.includes(*after) => Some(after.with_timezone(&Utc)),
+            Trigger::Cron(schedule) => schedule.after(after).next().map(|time| time.with_timezone(&Utc)),
+            Trigger::At(time) => Some(*time).filter(is_next),
+            Trigger::Sun { event, offset } => {
+                let location = self.location?;
+                // Starting from the day before, as the date in UTC might be behind the local date.
+                let first_date = after.with_timezone(&Utc).date_naive().pred_opt()?;
+                // Near the poles the sun might not rise or set for months.
+                return first_date.iter_days()
+                        .take(400)
+                        .map(|date| get_sun_event_time(location, date, *event) + *offset)
+                        .find(is_next);
+            },
+        }
+    }
+
+    /// Returns the first action after the given time, or None if nothing is scheduled after it.
+    pub fn get_next_action(&self, after: &DateTime<Local>) -> Option<ScheduledAction> {
+        return self.find_next_action(after, usize::MAX);
+    }
+
+    /// Returns the action that follows the given one. Actions of later lines at the same time
+    /// follow it too, so that they are not skipped.
+    pub fn get_action_after(&self, previous: &ScheduledAction) -> Option<ScheduledAction> {
+        return self.find_next_action(&previous.time, previous.line_number);
+    }
+
+    fn find_next_action(&self, after: &DateTime<Local>, after_line_number: usize) -> Option<ScheduledAction> {
+        return self.entries.iter()
+                .filter_map(|entry| {
+                    let time = self.get_next_time(&entry.trigger, after, entry.line_number > after_line_number)?;
+                    Some(ScheduledAction { time: time.with_timezone(&Local), action: entry.action, line_number: entry.line_number })
+                })
+                .min_by_key(|scheduled_action| (scheduled_action.time, scheduled_action.line_number));
+    }
+}
+
+/// Time before an action to wake the camera up, so that waking up doesn't delay the action.
+const WAKE_UP_AHEAD: Duration = Duration::from_secs(4);
+/// Longest sleep before checking the clock again, in case the host clock is adjusted.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+fn get_time_until(time: &DateTime<Local>) -> Duration {
+    return (*time - Local::now()).to_std().unwrap_or(Duration::ZERO);
+}
+
+/// Waits until the given time. The camera is left alone during long waits, and woken up shortly
+/// before the time, as it has most likely gone to sleep.
+fn wait_for<T: CameraInterface>(session: &mut Session<T>, time: &DateTime<Local>) {
+    let mut has_slept = false;
+    loop {
+        let remaining = get_time_until(time);
+        if remaining <= WAKE_UP_AHEAD {
+            break;
+        }
+        thread::sleep((remaining - WAKE_UP_AHEAD).min(MAX_SLEEP));
+        has_slept = true;
+    }
+    if has_slept {
+        if let Err(error) = session.start() {
+            eprintln!("Could not wake up the camera, trying again at the scheduled time: {:#}", error);
+        }
+    }
+    session.wait_until(Instant::now() + get_time_until(time));
+}
+
+/// Runs the given schedule file in the foreground, in one session that is kept open. Returns
+/// when nothing is scheduled anymore.
+pub fn run_schedule(serial_device: &String, schedule_file: &String, use_fast_session: bool) -> Result<()> {
+    let text = fs::read_to_string(schedule_file).with_context(|| format!("Could not read {}", schedule_file))?;
+    let schedule = Schedule::parse(&text)?;
+
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), use_fast_session);
+    session.start()?;
+
+    let mut next_action = schedule.get_next_action(&Local::now());
+    while let Some(scheduled_action) = next_action {
+        println!("Next: {:?} at {} (line {})", scheduled_action.action,
+                 scheduled_action.time.to_rfc3339_opts(SecondsFormat::Secs, false), scheduled_action.line_number);
+        wait_for(&mut session, &scheduled_action.time);
+
+        let focus_before_release = scheduled_action.action == Action::FocusAndShoot;
+        let result = timelapse::release(&mut session, focus_before_release);
+        let timestamp = Local::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+        match result {
+            Ok(()) => println!("[{}] Released", timestamp),
+            Err(error) => {
+                eprintln!("[{}] FAILED: {:#}", timestamp, error);
+                if let Err(error) = session.start() {
+                    eprintln!("Could not start a new session: {:#}", error);
+                }
+            },
+        }
+        next_action = schedule.get_action_after(&scheduled_action);
+    }
+
+    println!("Nothing more is scheduled.");
+    return session.close();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn local_time(value: &str) -> DateTime<Local> {
+        return session_commands::parse_local_time(value).unwrap().with_timezone(&Local);
+    }
+
+    #[test]
+    fn absolute_times_should_be_in_order() {
+        let schedule = Schedule::parse("at 2024-06-21 12:00 shoot\nat 2024-06-21 10:30:15 focus+shoot\n").unwrap();
+
+        let first = schedule.get_next_action(&local_time("2024-06-21 09:00")).unwrap();
+        assert_eq!(ScheduledAction { time: local_time("2024-06-21 10:30:15"), action: Action::FocusAndShoot, line_number: 2 }, first);
+        let second = schedule.get_next_action(&first.time).unwrap();
+        assert_eq!(ScheduledAction { time: local_time("2024-06-21 12:00"), action: Action::Shoot, line_number: 1 }, second);
+        assert_eq!(None, schedule.get_next_action(&second.time));
+    }
+
+    #[test]
+    fn actions_at_the_same_time_should_all_run() {
+        let schedule = Schedule::parse("cron 30 10 * * * shoot\nat 2024-06-21 10:30 focus+shoot\n").unwrap();
+
+        let first = schedule.get_next_action(&local_time("2024-06-21 09:00")).unwrap();
+        assert_eq!(ScheduledAction { time: local_time("2024-06-21 10:30"), action: Action::Shoot, line_number: 1 }, first);
+        let second = schedule.get_action_after(&first).unwrap();
+        assert_eq!(ScheduledAction { time: local_time("2024-06-21 10:30"), action: Action::FocusAndShoot, line_number: 2 }, second);
+        let third = schedule.get_action_after(&second).unwrap();
+        assert_eq!(ScheduledAction { time: local_time("2024-06-22 10:30"), action: Action::Shoot, line_number: 1 }, third);
+    }
+
+    #[test]
+    fn cron_expression_without_seconds_should_be_accepted() {
+        let schedule = Schedule::parse("cron */10 6-18 * * * shoot # Every 10 minutes").unwrap();
+
+        let next = schedule.get_next_action(&local_time("2024-06-21 05:55")).unwrap();
+        assert_eq!(local_time("2024-06-21 06:00"), next.time);
+        let next = schedule.get_next_action(&next.time).unwrap();
+        assert_eq!(local_time("2024-06-21 06:10"), next.time);
+        let next = schedule.get_next_action(&local_time("2024-06-21 18:55")).unwrap();
+        assert_eq!(local_time("2024-06-22 06:00"), next.time);
+    }
+
+    #[test]
+    fn sunrise_should_be_computed_from_the_location() {
+        // Sunrise at the equator and the prime meridian is around 06:00 UTC.
+        let schedule = Schedule::parse("location 0 0\nsunrise -30m shoot").unwrap();
+        let after = Utc.with_ymd_and_hms(2024, 3, 20, 0, 0, 0).unwrap().with_timezone(&Local);
+
+        let next = schedule.get_next_action(&after).unwrap().time.with_timezone(&Utc);
+        let expected = Utc.with_ymd_and_hms(2024, 3, 20, 5, 30, 0).unwrap();
+        assert!((next - expected).abs() < TimeDelta::minutes(10), "{}", next);
+    }
+
+    #[test]
+    fn sun_events_without_location_should_be_error() {
+        assert!(Schedule::parse("sunset +15m shoot").is_err());
+    }
+
+    #[test]
+    fn invalid_lines_should_be_errors() {
+        assert!(Schedule::parse("at 2024-06-21 shoot").is_err());
+        assert!(Schedule::parse("cron * * * shoot").is_err());
+        assert!(Schedule::parse("at 2024-06-21 12:00 rewind").is_err());
+        assert!(Schedule::parse("every 5m shoot").is_err());
+        assert!(Schedule::parse("location 91 0").is_err());
+    }
+
+    #[test]
+    fn offsets_should_have_a_sign() {
+        assert_eq!(TimeDelta::minutes(-30), parse_offset("-30m").unwrap());
+        assert_eq!(TimeDelta::hours(1), parse_offset("+1h").unwrap());
+        assert_eq!(TimeDelta::seconds(90), parse_offset("90").unwrap());
+    }
+}
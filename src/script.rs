@@ -0,0 +1,107 @@
+use crate::address_map::AddressMap;
+use crate::journal::Journal;
+use crate::session_commands::{self, SessionCommand};
+use f90x_tool::camera_interface::{self, CameraInterface, SerialCameraConnection};
+use f90x_tool::session::Session;
+
+use anyhow::{Context, Result, anyhow};
+use std::fs;
+
+/// Runs the parsed commands of a script in the session, printing the result of each line.
+/// Returns the number of failed lines.
+fn run_commands<T: CameraInterface>(
+        session: &mut Session<T>,
+        address_map: &AddressMap,
+        journal: &Journal,
+        commands: &[(usize, &str, SessionCommand)],
+        keep_going: bool) -> usize {
+    let mut num_failed_lines = 0;
+    for (line_number, line, command) in commands {
+        if *command == SessionCommand::Exit {
+            break;
+        }
+        println!("[line {}] {}", line_number, line);
+        match session_commands::execute(session, address_map, journal, command) {
+            Ok(()) => println!("[line {}] OK", line_number),
+            Err(error) => {
+                println!("[line {}] FAILED: {:#}", line_number, error);
+                num_failed_lines += 1;
+                if !keep_going {
+                    break;
+                }
+            },
+        }
+    }
+    return num_failed_lines;
+}
+
+/// Runs the commands in the given script file in one camera session.
+///
+/// The script has one command per line, in the same format as the interactive shell. The script
+/// stops at the first failing line, unless `keep_going` is set.
+pub fn run_script(
+        serial_device: &String,
+        address_map: &AddressMap,
+        journal: &Journal,
+        script_file: &String,
+        keep_going: bool,
+        use_fast_session: bool) -> Result<()> {
+    let script = fs::read_to_string(script_file)
+            .with_context(|| format!("Could not read the script \"{}\"", script_file))?;
+    // Parse everything first, so that a typo doesn't stop the script in the middle.
+    let mut commands: Vec<(usize, &str, SessionCommand)> = Vec::new();
+    for (index, line) in script.lines().enumerate() {
+        let line_number = index + 1;
+        let command = SessionCommand::parse(line, address_map).with_context(|| format!("Line {}", line_number))?;
+        if let Some(command) = command {
+            commands.push((line_number, line.trim(), command));
+        }
+    }
+
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), use_fast_session);
+    session.start()?;
+
+    let num_failed_lines = run_commands(&mut session, address_map, journal, &commands, keep_going);
+    session.close()?;
+    if num_failed_lines > 0 {
+        return Err(anyhow!("{} line(s) of the script failed", num_failed_lines));
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use f90x_tool::camera_interface::fake::FakeCamera;
+    use f90x_tool::camera_interface::messaging::CameraCommand;
+    use f90x_tool::camera_interface::models::F90X_PROFILE;
+
+    fn run_test_script(keep_going: bool) -> (usize, Session<FakeCamera>) {
+        let address_map = AddressMap::load_with_defaults(None, &F90X_PROFILE).unwrap();
+        let journal = Journal::open(Some(&String::from("/nonexistent/journal.jsonl")), "/dev/ttyUSB0").unwrap();
+        let commands = vec![
+            (1, "expect 0xFD40 0x40", SessionCommand::Expect { address: 0xFD40, values: vec![0x40] }),
+            (2, "expect 0xFD40 0x5F", SessionCommand::Expect { address: 0xFD40, values: vec![0x5F] }),
+            (3, "shoot", SessionCommand::Shoot),
+        ];
+        let mut session = Session::new(FakeCamera::with_address_pattern(), false);
+        session.start().unwrap();
+        let num_failed_lines = run_commands(&mut session, &address_map, &journal, &commands, keep_going);
+        return (num_failed_lines, session);
+    }
+
+    #[test]
+    fn script_should_stop_at_the_first_failing_line() {
+        let (num_failed_lines, mut session) = run_test_script(false);
+        assert_eq!(1, num_failed_lines);
+        assert!(!session.get_camera().unwrap().sent_commands.contains(&CameraCommand::Shoot));
+    }
+
+    #[test]
+    fn script_should_run_every_line_with_keep_going() {
+        let (num_failed_lines, mut session) = run_test_script(true);
+        assert_eq!(1, num_failed_lines);
+        assert!(session.get_camera().unwrap().sent_commands.contains(&CameraCommand::Shoot));
+    }
+}
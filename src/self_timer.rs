@@ -0,0 +1,126 @@
+use f90x_tool::camera_interface::{self, CameraInterface, SerialCameraConnection};
+use f90x_tool::camera_operations;
+use f90x_tool::session::Session;
+
+use anyhow::{Context, Result, anyhow};
+use log::warn;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Settings of a delayed or repeated release.
+pub struct SelfTimerOptions {
+    pub delay: Duration,
+    pub count: u32,
+    pub gap: Duration,
+    /// Time before each release to trigger auto-focus, if at all.
+    pub focus_before: Option<Duration>,
+}
+
+impl SelfTimerOptions {
+    /// Returns true if the options are for one release without a delay.
+    pub fn is_single_release(&self) -> bool {
+        return self.delay.is_zero() && self.count == 1 && self.focus_before.is_none();
+    }
+}
+
+/// Time between the updates of the countdown.
+const TICK: Duration = Duration::from_millis(100);
+/// Nothing is sent to keep the camera awake in this time before a release.
+const QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+/// Formats the remaining time before the release, rounded up to whole seconds.
+fn format_countdown(frame: u32, count: u32, remaining: Duration) -> String {
+    let seconds = remaining.as_millis().div_ceil(1000);
+    if count == 1 {
+        return format!("Releasing in {}s", seconds);
+    }
+    return format!("Frame {}/{}: releasing in {}s", frame, count, seconds);
+}
+
+/// Shows the countdown to `release_time` until `until`. Fails if cancelled.
+fn count_down<T: CameraInterface>(
+        session: &mut Session<T>,
+        until: Instant,
+        release_time: Instant,
+        frame: u32,
+        count: u32,
+        cancelled: &AtomicBool) -> Result<()> {
+    let mut last_line = String::new();
+    loop {
+        if cancelled.load(Ordering::SeqCst) {
+            println!();
+            return Err(anyhow!("Cancelled"));
+        }
+        let now = Instant::now();
+        if now >= until {
+            return Ok(());
+        }
+        let line = format_countdown(frame, count, release_time.saturating_duration_since(now));
+        if line != last_line {
+            print!("\r{} ", line);
+            std::io::stdout().flush()?;
+            last_line = line;
+        }
+        if now + QUIET_PERIOD < release_time {
+            if let Err(error) = session.keep_alive() {
+                warn!("Could not keep the camera awake: {:#}", error);
+            }
+        }
+        thread::sleep(until.saturating_duration_since(Instant::now()).min(TICK));
+    }
+}
+
+/// Releases the shutter after a delay, or several times with a gap in between, showing a
+/// countdown. Ctrl-C cancels the remaining releases.
+pub fn release_with_self_timer(serial_device: &String, options: &SelfTimerOptions) -> Result<()> {
+    if options.count == 0 {
+        return Err(anyhow!("The count should be at least 1"));
+    }
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let handler_flag = cancelled.clone();
+    ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst))
+            .context("Could not set the Ctrl-C handler")?;
+
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), false);
+    session.start()?;
+
+    let result = run_releases(&mut session, options, &cancelled);
+    session.close()?;
+    return result;
+}
+
+fn run_releases<T: CameraInterface>(session: &mut Session<T>, options: &SelfTimerOptions, cancelled: &AtomicBool) -> Result<()> {
+    let start = Instant::now();
+    for frame_index in 0..options.count {
+        let frame = frame_index + 1;
+        // Counted from the start, so that the time of a release doesn't delay the next ones.
+        let release_time = start + options.delay + options.gap * frame_index;
+
+        if let Some(focus_before) = options.focus_before {
+            let focus_time = release_time.checked_sub(focus_before).unwrap_or(start);
+            count_down(session, focus_time, release_time, frame, options.count, cancelled)?;
+            camera_operations::autofocus(session.get_camera()?).context("Auto-focus failed")?;
+        }
+        count_down(session, release_time, release_time, frame, options.count, cancelled)?;
+        camera_operations::release_shutter(session.get_camera()?)?;
+        println!("\rFrame {}/{} released          ", frame, options.count);
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn countdown_should_round_up_to_seconds() {
+        assert_eq!("Releasing in 10s", format_countdown(1, 1, Duration::from_millis(9001)));
+        assert_eq!("Releasing in 1s", format_countdown(1, 1, Duration::from_millis(1)));
+        assert_eq!("Releasing in 0s", format_countdown(1, 1, Duration::ZERO));
+        assert_eq!("Frame 2/3: releasing in 2s", format_countdown(2, 3, Duration::from_secs(2)));
+    }
+}
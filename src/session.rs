@@ -0,0 +1,306 @@
+use crate::camera_interface::CameraInterface;
+use crate::camera_interface::models::CameraInfo;
+
+use anyhow::{Result, anyhow};
+use log::{debug, warn};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time without communication after which the camera might have gone to sleep.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A camera session that is kept open between commands.
+///
+/// If the session was idle long enough for the camera to go to sleep, the session is started
+/// again before the camera is used.
+pub struct Session<T: CameraInterface> {
+    camera: T,
+    camera_info: Option<CameraInfo>,
+    use_fast_session: bool,
+    idle_timeout: Duration,
+    last_activity: Instant,
+}
+
+impl<T: CameraInterface> Session<T> {
+    /// Creates a session for the given camera. The session is started with [Session::start].
+    pub fn new(camera: T, use_fast_session: bool) -> Session<T> {
+        return Session {
+            camera,
+            camera_info: None,
+            use_fast_session,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            last_activity: Instant::now(),
+        };
+    }
+
+    /// Sets the time without communication after which the session is started again.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// Starts a new session with the camera, upgrading it to a fast session if needed. If the
+    /// connection can't change its BAUD rate, the default BAUD rate is used instead.
+    pub fn start(&mut self) -> Result<&CameraInfo> {
+        if self.use_fast_session && !self.camera.supports_fast_session() {
+            warn!("The connection can't change its BAUD rate, using the default BAUD rate");
+            self.use_fast_session = false;
+        }
+        if self.camera_info.is_some() && self.use_fast_session {
+            // The camera might still be awake in the fast session, or might have gone to sleep.
+            if let Err(error) = self.camera.end_fast_session() {
+                debug!("Could not end the previous fast session: {:#}", error);
+            }
+        }
+        self.camera_info = None;
+        let camera_info = self.camera.start_new_session()?;
+        if self.use_fast_session {
+            self.camera.upgrade_to_fast_session()?;
+        }
+        self.last_activity = Instant::now();
+        return Ok(self.camera_info.insert(camera_info));
+    }
+
+    /// Returns the camera info from the unit inquiry, if the session was started.
+    pub fn get_camera_info(&self) -> Option<&CameraInfo> {
+        return self.camera_info.as_ref();
+    }
+
+    /// Returns the camera to send commands to. The session is started again if it was idle for
+    /// too long.
+    pub fn get_camera(&mut self) -> Result<&mut T> {
+        if self.camera_info.is_none() {
+            return Err(anyhow!("The session is not started"));
+        }
+        if self.last_activity.elapsed() >= self.idle_timeout {
+            debug!("Session was idle for {:?}, starting it again", self.last_activity.elapsed());
+            self.start()?;
+        }
+        self.last_activity = Instant::now();
+        return Ok(&mut self.camera);
+    }
+
+    /// Keeps the camera awake by starting the session again, if it was idle for half of the idle
+    /// timeout.
+    pub fn keep_alive(&mut self) -> Result<()> {
+        if self.camera_info.is_some() && self.last_activity.elapsed() >= self.idle_timeout / 2 {
+            debug!("Keeping the camera awake");
+            self.start()?;
+        }
+        return Ok(());
+    }
+
+    /// Waits until the given time, keeping the camera awake meanwhile. Nothing is sent to the
+    /// camera in the last second before the given time, so that the camera is ready right then.
+    pub fn wait_until(&mut self, deadline: Instant) {
+        const QUIET_PERIOD: Duration = Duration::from_secs(1);
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return;
+            }
+            if remaining <= QUIET_PERIOD {
+                thread::sleep(remaining);
+                return;
+            }
+            thread::sleep((remaining - QUIET_PERIOD).min(QUIET_PERIOD));
+            if Instant::now() + QUIET_PERIOD < deadline {
+                if let Err(error) = self.keep_alive() {
+                    warn!("Could not keep the camera awake: {:#}", error);
+                }
+            }
+        }
+    }
+
+    pub fn is_fast_session(&self) -> bool {
+        return self.use_fast_session;
+    }
+
+    /// Switches between the fast and the default BAUD rate sessions.
+    pub fn set_fast_session(&mut self, use_fast_session: bool) -> Result<()> {
+        if use_fast_session == self.use_fast_session {
+            return Ok(());
+        }
+        let camera = self.get_camera()?;
+        if use_fast_session {
+            camera.upgrade_to_fast_session()?;
+        } else {
+            camera.end_fast_session()?;
+        }
+        self.use_fast_session = use_fast_session;
+        return Ok(());
+    }
+
+    /// Closes the session. A fast session is ended, so that the camera goes back to the default
+    /// BAUD rate.
+    pub fn close(&mut self) -> Result<()> {
+        let is_camera_awake = self.last_activity.elapsed() < self.idle_timeout;
+        if self.camera_info.take().is_some() && self.use_fast_session && is_camera_awake {
+            self.camera.end_fast_session()?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_interface::MockCameraInterface;
+    use mockall::Sequence;
+
+    fn f90x_info() -> CameraInfo {
+        return CameraInfo { unit_code: String::from("1020"), model_name: String::from("F90X/N90S") };
+    }
+
+    #[test]
+    fn camera_should_not_be_available_before_starting() {
+        let mut session = Session::new(MockCameraInterface::new(), false);
+        assert!(session.get_camera().is_err());
+    }
+
+    #[test]
+    fn start_should_upgrade_to_fast_session() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_start_new_session()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Ok(f90x_info()));
+        mock_camera.expect_upgrade_to_fast_session()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Ok(()));
+        mock_camera.expect_supports_fast_session().return_const(true);
+
+        let mut session = Session::new(mock_camera, true);
+        assert_eq!("F90X/N90S", session.start().unwrap().model_name);
+    }
+
+    #[test]
+    fn fast_session_should_fall_back_to_default_baud_rate_if_not_supported() {
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_supports_fast_session().return_const(false);
+        mock_camera.expect_start_new_session()
+                   .times(1)
+                   .returning(|| Ok(f90x_info()));
+        mock_camera.expect_upgrade_to_fast_session().times(0);
+        mock_camera.expect_end_fast_session().times(0);
+
+        let mut session = Session::new(mock_camera, true);
+        session.start().unwrap();
+        assert!(!session.is_fast_session());
+        session.close().unwrap();
+    }
+
+    #[test]
+    fn session_should_not_be_started_again_while_it_is_active() {
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_start_new_session()
+                   .times(1)
+                   .returning(|| Ok(f90x_info()));
+
+        let mut session = Session::new(mock_camera, false);
+        session.start().unwrap();
+        assert!(session.get_camera().is_ok());
+        assert!(session.get_camera().is_ok());
+    }
+
+    #[test]
+    fn idle_fast_session_should_be_started_again() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_start_new_session()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Ok(f90x_info()));
+        mock_camera.expect_upgrade_to_fast_session()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Ok(()));
+        // The camera was asleep
+        mock_camera.expect_end_fast_session()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Err(anyhow!("")));
+        mock_camera.expect_start_new_session()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Ok(f90x_info()));
+        mock_camera.expect_upgrade_to_fast_session()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Ok(()));
+        mock_camera.expect_supports_fast_session().return_const(true);
+
+        let mut session = Session::new(mock_camera, true);
+        session.start().unwrap();
+        session.set_idle_timeout(Duration::ZERO);
+        assert!(session.get_camera().is_ok());
+    }
+
+    #[test]
+    fn keep_alive_should_start_idle_session_again() {
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_start_new_session()
+                   .times(2)
+                   .returning(|| Ok(f90x_info()));
+
+        let mut session = Session::new(mock_camera, false);
+        session.keep_alive().unwrap();
+        session.start().unwrap();
+        session.keep_alive().unwrap();
+        session.set_idle_timeout(Duration::ZERO);
+        session.keep_alive().unwrap();
+    }
+
+    #[test]
+    fn wait_until_should_return_at_the_given_time() {
+        let mut session = Session::new(MockCameraInterface::new(), false);
+        let deadline = Instant::now() + Duration::from_millis(50);
+        session.wait_until(deadline);
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    fn close_should_end_fast_session() {
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_start_new_session()
+                   .returning(|| Ok(f90x_info()));
+        mock_camera.expect_upgrade_to_fast_session()
+                   .returning(|| Ok(()));
+        mock_camera.expect_end_fast_session()
+                   .times(1)
+                   .returning(|| Ok(()));
+        mock_camera.expect_supports_fast_session().return_const(true);
+
+        let mut session = Session::new(mock_camera, true);
+        session.start().unwrap();
+        assert!(session.close().is_ok());
+        assert!(session.close().is_ok());
+    }
+
+    #[test]
+    fn fast_session_should_be_switched_on_and_off() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_start_new_session()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Ok(f90x_info()));
+        mock_camera.expect_upgrade_to_fast_session()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Ok(()));
+        mock_camera.expect_end_fast_session()
+                   .times(1)
+                   .in_sequence(&mut sequence)
+                   .returning(|| Ok(()));
+
+        let mut session = Session::new(mock_camera, false);
+        session.start().unwrap();
+        session.set_fast_session(true).unwrap();
+        assert!(session.is_fast_session());
+        session.set_fast_session(false).unwrap();
+        assert!(!session.is_fast_session());
+        assert!(session.close().is_ok());
+    }
+}
@@ -0,0 +1,379 @@
+use crate::address_map::AddressMap;
+use crate::journal::{self, Journal};
+use f90x_tool::camera_operations;
+use f90x_tool::camera_interface::CameraInterface;
+use f90x_tool::session::Session;
+use f90x_tool::shooting_data;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use std::thread;
+use std::time::Duration;
+
+/// Names of the commands, for completion.
+pub const COMMAND_NAMES: &[&str] = &["read", "write", "focus", "shoot", "fast", "memo", "dump", "sleep", "expect", "reconnect", "help", "exit"];
+
+pub const HELP: &str = "\
+read <address> [length] [memory space]   Reads memory. Prefix with 0x for hex values.
+write <address> <value>...               Writes to the \"0\" memory space.
+focus                                    Triggers auto-focus.
+shoot                                    Releases the shutter.
+fast on|off                              Switches between 9600 and 1200 BAUD rate.
+memo status                              Prints the memo holder status.
+dump <address> <length> [memory space]   Prints a memory range as a hex dump.
+sleep <duration>                         Waits, for example \"500ms\", \"2s\" or \"1m\".
+expect <address> <value>...              Fails if the \"0\" memory space has other values.
+reconnect                                Starts a new session, for example after the camera was off.
+help                                     Prints this help.
+exit                                     Closes the session.
+The addresses can also be names from the address map, like \"memo.setting\", and the values
+of a named location can be given by name, like \"full\".";
+
+/// A command that can be given in the interactive shell.
+#[derive(Debug, PartialEq)]
+pub enum SessionCommand {
+    Read { address: u16, length: u8, memory_space: u8 },
+    Write { address: u16, values: Vec<u8> },
+    Focus,
+    Shoot,
+    Fast(bool),
+    MemoStatus,
+    Dump { address: u16, length: usize, memory_space: u8 },
+    Sleep(Duration),
+    Expect { address: u16, values: Vec<u8> },
+    Reconnect,
+    Help,
+    Exit,
+}
+
+impl SessionCommand {
+    /// Parses a command line. Returns None for empty lines. Everything after a "#" is a comment.
+    /// The addresses are resolved with the address map, and without a length or a memory space,
+    /// the ones of a named location are used.
+    pub fn parse(line: &str, address_map: &AddressMap) -> Result<Option<SessionCommand>> {
+        let line = line.split('#').next().unwrap_or_default();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, arguments)) = words.split_first() else {
+            return Ok(None);
+        };
+
+        let command = match name {
+            "read" => {
+                expect_argument_count(name, arguments, 1, 3)?;
+                let resolved = address_map.resolve(arguments[0])?;
+                SessionCommand::Read {
+                    address: resolved.address,
+                    length: arguments.get(1).map_or_else(|| resolved.get_read_length(), |value| parse_number(value))?,
+                    memory_space: arguments.get(2).map_or(Ok(resolved.get_memory_space()), |value| parse_number(value))?,
+                }
+            },
+            "write" => {
+                expect_argument_count(name, arguments, 2, 256)?;
+                let resolved = address_map.resolve_in_space(arguments[0], 0)?;
+                SessionCommand::Write { address: resolved.address, values: address_map.parse_values(&resolved, &arguments[1..])? }
+            },
+            "focus" => {
+                expect_argument_count(name, arguments, 0, 0)?;
+                SessionCommand::Focus
+            },
+            "shoot" => {
+                expect_argument_count(name, arguments, 0, 0)?;
+                SessionCommand::Shoot
+            },
+            "fast" => match arguments {
+                ["on"] => SessionCommand::Fast(true),
+                ["off"] => SessionCommand::Fast(false),
+                _ => return Err(anyhow!("Usage: fast on|off")),
+            },
+            "memo" => match arguments {
+                ["status"] => SessionCommand::MemoStatus,
+                _ => return Err(anyhow!("Usage: memo status")),
+            },
+            "dump" => {
+                expect_argument_count(name, arguments, 2, 3)?;
+                let resolved = address_map.resolve(arguments[0])?;
+                SessionCommand::Dump {
+                    address: resolved.address,
+                    length: parse_number(arguments[1])?,
+                    memory_space: arguments.get(2).map_or(Ok(resolved.get_memory_space()), |value| parse_number(value))?,
+                }
+            },
+            "sleep" => {
+                expect_argument_count(name, arguments, 1, 1)?;
+                SessionCommand::Sleep(parse_duration(arguments[0])?)
+            },
+            "expect" => {
+                expect_argument_count(name, arguments, 2, 256)?;
+                let resolved = address_map.resolve_in_space(arguments[0], 0)?;
+                SessionCommand::Expect { address: resolved.address, values: address_map.parse_values(&resolved, &arguments[1..])? }
+            },
+            "reconnect" => {
+                expect_argument_count(name, arguments, 0, 0)?;
+                SessionCommand::Reconnect
+            },
+            "help" => SessionCommand::Help,
+            "exit" | "quit" => SessionCommand::Exit,
+            _ => return Err(anyhow!("Unknown command \"{}\". Type \"help\" for the commands.", name)),
+        };
+        return Ok(Some(command));
+    }
+}
+
+fn expect_argument_count(name: &str, arguments: &[&str], min: usize, max: usize) -> Result<()> {
+    if arguments.len() < min || arguments.len() > max {
+        return Err(anyhow!("Wrong number of arguments for \"{}\". Type \"help\" for the usage.", name));
+    }
+    return Ok(());
+}
+
+/// Parses a decimal number, or a hex number prefixed with 0x.
+pub fn parse_number<T: TryFrom<u64>>(value: &str) -> Result<T> {
+    let number = clap_num::maybe_hex::<u64>(value).map_err(|error| anyhow!("Invalid number \"{}\": {}", value, error))?;
+    return T::try_from(number).map_err(|_| anyhow!("Number \"{}\" is out of range", value));
+}
+
+/// Parses a duration like "500ms", "2s", "1.5m" or "1h". A number without a unit is in seconds.
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let split_index = value.find(|character: char| character.is_ascii_alphabetic()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split_index);
+    let number: f64 = number.parse().map_err(|_| anyhow!("Invalid duration \"{}\"", value))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" | "" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(anyhow!("Unknown unit in duration \"{}\". Use ms, s, m or h.", value)),
+    };
+    return Duration::try_from_secs_f64(seconds).map_err(|_| anyhow!("Invalid duration \"{}\"", value));
+}
+
+/// Parses a local time like "2024-05-01 14:30" or "2024-05-01 14:30:15".
+pub fn parse_local_time(value: &str) -> Result<DateTime<Utc>> {
+    let time = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M"))
+            .map_err(|_| anyhow!("Invalid time \"{}\". Use \"YYYY-MM-DD HH:MM[:SS]\".", value))?;
+    let time = Local.from_local_datetime(&time)
+            .earliest()
+            .ok_or(anyhow!("Time \"{}\" doesn't exist in the local time zone", value))?;
+    return Ok(time.with_timezone(&Utc));
+}
+
+/// Executes the given command in the session. Writes to the protected locations of the address
+/// map are refused, and the other writes are recorded in the journal.
+pub fn execute<T: CameraInterface>(
+        session: &mut Session<T>,
+        address_map: &AddressMap,
+        journal: &Journal,
+        command: &SessionCommand) -> Result<()> {
+    match command {
+        SessionCommand::Read { address, length, memory_space } => {
+            let values = camera_operations::read_memory(session.get_camera()?, *memory_space, *address, *length)?;
+            println!("Memory value: {:02X?}", &values);
+        },
+        SessionCommand::Write { address, values } => {
+            journal::write_with_journal(session, journal, address_map, *address, values.clone())?;
+            println!("Successfully written and verified.");
+        },
+        SessionCommand::Focus => camera_operations::autofocus(session.get_camera()?)?,
+        SessionCommand::Shoot => camera_operations::release_shutter(session.get_camera()?)?,
+        SessionCommand::Fast(use_fast_session) => session.set_fast_session(*use_fast_session)?,
+        SessionCommand::MemoStatus => {
+            let profile = session.get_camera_info().ok_or(anyhow!("The session is not started"))?.get_profile()?;
+            let status = shooting_data::get_memo_holder_status(session.get_camera()?, &profile.memory_map)?;
+            print_memo_holder_status(&status);
+        },
+        SessionCommand::Dump { address, length, memory_space } => {
+            let values = camera_operations::read_memory_range(session.get_camera()?, *memory_space, *address, *length)?;
+            print!("{}", format_hex_dump(*address, &values));
+        },
+        SessionCommand::Sleep(duration) => thread::sleep(*duration),
+        SessionCommand::Expect { address, values } => {
+            let length = u8::try_from(values.len()).map_err(|_| anyhow!("Too many values given."))?;
+            let actual_values = camera_operations::read_memory(session.get_camera()?, 0, *address, length)?;
+            if &actual_values != values {
+                return Err(anyhow!("Expected {:02X?} at {:04X}, but the memory value is {:02X?}",
+                                   values, address, actual_values));
+            }
+            println!("Memory value matches: {:02X?}", &actual_values);
+        },
+        SessionCommand::Reconnect => {
+            let camera_info = session.start()?;
+            println!("Connected to {}.", camera_info.model_name);
+        },
+        SessionCommand::Help => println!("{}", HELP),
+        SessionCommand::Exit => (),
+    }
+    return Ok(());
+}
+
+pub fn print_memo_holder_status(status: &shooting_data::MemoHolderStatus) {
+    print!("{}", format_memo_holder_status(status));
+}
+
+pub fn format_memo_holder_status(status: &shooting_data::MemoHolderStatus) -> String {
+    return format!("Setting: {:?} ({} bytes per frame)\n", status.setting, status.setting.get_bytes_per_frame())
+            + &format!("Ring buffer: {:04X} - {:04X}\n", status.ring_buffer.start, status.ring_buffer.end)
+            + &format!("Data start: {:04X}, current roll start: {:04X}, current: {:04X}\n",
+                       status.addresses.start, status.addresses.current_roll_start, status.addresses.current)
+            + &format!("Has finished roll to read: {}\n", shooting_data::has_finished_roll_to_read(&status.addresses))
+            + &format!("Roll: {}, bytes to read: {}\n", status.info.roll_id, status.info.bytes_to_read);
+}
+
+/// Formats the given bytes as 16 bytes per line, prefixed with the address.
+pub fn format_hex_dump(start_address: u16, values: &[u8]) -> String {
+    let mut dump = String::new();
+    for (index, line) in values.chunks(16).enumerate() {
+        let address = (start_address as usize) + index * 16;
+        let bytes: Vec<String> = line.iter().map(|value| format!("{:02X}", value)).collect();
+        dump += &format!("{:04X}: {}\n", address, bytes.join(" "));
+    }
+    return dump;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use f90x_tool::camera_interface::models::F90X_PROFILE;
+    use f90x_tool::camera_interface::fake::FakeCamera;
+
+    fn parse(line: &str) -> Result<Option<SessionCommand>> {
+        return SessionCommand::parse(line, &AddressMap::load_with_defaults(None, &F90X_PROFILE).unwrap());
+    }
+
+    fn test_journal(name: &str) -> (Journal, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("f90x-tool-{}-{}.jsonl", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        return (Journal::open(Some(&path.to_string_lossy().to_string()), "/dev/ttyUSB0").unwrap(), path);
+    }
+
+    #[test]
+    fn read_command_should_be_parsed_with_defaults() {
+        let command = parse("read 0xFD40").unwrap();
+        assert_eq!(Some(SessionCommand::Read { address: 0xFD40, length: 1, memory_space: 0 }), command);
+
+        let command = parse("  read 0xFD40 6 1 ").unwrap();
+        assert_eq!(Some(SessionCommand::Read { address: 0xFD40, length: 6, memory_space: 1 }), command);
+    }
+
+    #[test]
+    fn write_command_should_be_parsed() {
+        let command = parse("write 0x10 0x01 2").unwrap();
+        assert_eq!(Some(SessionCommand::Write { address: 0x10, values: vec![0x01, 0x02] }), command);
+        assert!(parse("write 0x10").is_err());
+        assert!(parse("write 0x10 0x100").is_err());
+    }
+
+    #[test]
+    fn named_addresses_should_be_resolved() {
+        let command = parse("read memo.current").unwrap();
+        assert_eq!(Some(SessionCommand::Read { address: 0xFD42, length: 2, memory_space: 0 }), command);
+        let command = parse("write memo.setting full").unwrap();
+        assert_eq!(Some(SessionCommand::Write { address: 0xFD40, values: vec![0x5F] }), command);
+        let command = parse("expect memo.setting minimum").unwrap();
+        assert_eq!(Some(SessionCommand::Expect { address: 0xFD40, values: vec![0x45] }), command);
+        let command = parse("dump memo.setting 8").unwrap();
+        assert_eq!(Some(SessionCommand::Dump { address: 0xFD40, length: 8, memory_space: 0 }), command);
+        assert!(parse("read memo.unknown").is_err());
+        assert!(parse("write 0xFD40 full").is_err());
+    }
+
+    #[test]
+    fn fast_and_memo_commands_should_be_parsed() {
+        assert_eq!(Some(SessionCommand::Fast(true)), parse("fast on").unwrap());
+        assert_eq!(Some(SessionCommand::Fast(false)), parse("fast off").unwrap());
+        assert!(parse("fast").is_err());
+        assert_eq!(Some(SessionCommand::MemoStatus), parse("memo status").unwrap());
+    }
+
+    #[test]
+    fn empty_line_should_be_no_command() {
+        assert_eq!(None, parse("   ").unwrap());
+        assert_eq!(None, parse("# Only a comment").unwrap());
+    }
+
+    #[test]
+    fn comment_after_command_should_be_ignored() {
+        assert_eq!(Some(SessionCommand::Shoot), parse("shoot # Frame 1").unwrap());
+    }
+
+    #[test]
+    fn sleep_and_expect_commands_should_be_parsed() {
+        assert_eq!(Some(SessionCommand::Sleep(Duration::from_millis(1500))), parse("sleep 1.5s").unwrap());
+        let command = parse("expect 0xFD40 0x45").unwrap();
+        assert_eq!(Some(SessionCommand::Expect { address: 0xFD40, values: vec![0x45] }), command);
+    }
+
+    #[test]
+    fn durations_should_be_parsed_with_units() {
+        assert_eq!(Duration::from_millis(500), parse_duration("500ms").unwrap());
+        assert_eq!(Duration::from_secs(30), parse_duration("30s").unwrap());
+        assert_eq!(Duration::from_secs(30), parse_duration("30").unwrap());
+        assert_eq!(Duration::from_secs(90), parse_duration("1.5m").unwrap());
+        assert_eq!(Duration::from_secs(7200), parse_duration("2h").unwrap());
+        assert!(parse_duration("2d").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+
+    #[test]
+    fn local_times_should_be_parsed_with_optional_seconds() {
+        let time = parse_local_time("2024-05-01 14:30").unwrap().with_timezone(&Local);
+        assert_eq!("2024-05-01 14:30:00", time.format("%Y-%m-%d %H:%M:%S").to_string());
+        assert!(parse_local_time("2024-05-01 14:30:15").is_ok());
+        assert!(parse_local_time("2024-05-01").is_err());
+        assert!(parse_local_time("14:30").is_err());
+    }
+
+    #[test]
+    fn unknown_command_should_be_error() {
+        assert!(parse("rewind").is_err());
+        assert!(parse("shoot now").is_err());
+    }
+
+    #[test]
+    fn hex_dump_should_have_16_bytes_per_line() {
+        let values: Vec<u8> = (0..18).collect();
+        let expected = "FD00: 00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F\nFD10: 10 11\n";
+        assert_eq!(expected, format_hex_dump(0xFD00, &values));
+    }
+
+    #[test]
+    fn reconnect_should_start_the_session() {
+        let address_map = AddressMap::load_with_defaults(None, &F90X_PROFILE).unwrap();
+        let (journal, _) = test_journal("reconnect-test");
+        let mut session = Session::new(FakeCamera::with_address_pattern(), false);
+        assert!(session.get_camera().is_err());
+
+        assert_eq!(Some(SessionCommand::Reconnect), parse("reconnect").unwrap());
+        execute(&mut session, &address_map, &journal, &SessionCommand::Reconnect).unwrap();
+        assert!(session.get_camera().is_ok());
+    }
+
+    #[test]
+    fn writes_to_protected_locations_should_be_refused() {
+        let address_map = AddressMap::load_with_defaults(None, &F90X_PROFILE).unwrap();
+        let (journal, path) = test_journal("protected-write-test");
+        let mut session = Session::new(FakeCamera::with_address_pattern(), false);
+        session.start().unwrap();
+
+        let command = parse("write 0xFD00 0x00 0x00").unwrap().unwrap();
+        assert!(execute(&mut session, &address_map, &journal, &command).is_err());
+        assert!(session.get_camera().unwrap().get_writes().is_empty());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn writes_should_be_recorded_in_the_journal() {
+        let address_map = AddressMap::load_with_defaults(None, &F90X_PROFILE).unwrap();
+        let (journal, path) = test_journal("journaled-write-test");
+        let mut session = Session::new(FakeCamera::with_address_pattern(), false);
+        session.start().unwrap();
+
+        let command = parse("write 0xFD40 0x5F").unwrap().unwrap();
+        execute(&mut session, &address_map, &journal, &command).unwrap();
+        assert_eq!(vec![(0xFD40, vec![0x5F])], session.get_camera().unwrap().get_writes());
+        let journal_text = std::fs::read_to_string(&path).unwrap();
+        assert!(journal_text.contains("\"original_values\":[64],\"written_values\":[95]"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
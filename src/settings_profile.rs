@@ -0,0 +1,416 @@
+use crate::address_map::AddressMap;
+use crate::journal::{Journal, JournaledCamera};
+use f90x_tool::bracketing;
+use f90x_tool::camera_interface::{self, CameraInterface, SerialCameraConnection};
+use f90x_tool::camera_interface::models::CameraProfile;
+use f90x_tool::camera_settings::{AfMode, Aperture, CameraSettings, ExposureMode, FilmAdvanceMode, FlashSyncMode, MeteringMode, ShutterSpeed};
+use f90x_tool::session::Session;
+use f90x_tool::shooting_data::{self, MemoHolderSetting};
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+const EXPOSURE_MODES: &[ExposureMode] = &[
+    ExposureMode::ProgramAuto, ExposureMode::ShutterPriority, ExposureMode::AperturePriority, ExposureMode::Manual,
+];
+const METERING_MODES: &[MeteringMode] = &[MeteringMode::Matrix, MeteringMode::CenterWeighted, MeteringMode::Spot];
+const AF_MODES: &[AfMode] = &[AfMode::Single, AfMode::Continuous, AfMode::Manual];
+const FILM_ADVANCE_MODES: &[FilmAdvanceMode] = &[
+    FilmAdvanceMode::Single, FilmAdvanceMode::ContinuousLow, FilmAdvanceMode::ContinuousHigh,
+];
+const FLASH_SYNC_MODES: &[FlashSyncMode] = &[
+    FlashSyncMode::FrontCurtain, FlashSyncMode::SlowSync, FlashSyncMode::RearCurtain, FlashSyncMode::RedEyeReduction,
+];
+const MEMO_HOLDER_SETTINGS: &[MemoHolderSetting] = &[
+    MemoHolderSetting::DoNotStore, MemoHolderSetting::Minimum, MemoHolderSetting::Intermediate, MemoHolderSetting::Full,
+];
+
+/// Exposure compensation, as a number or as a fraction like "-2/3".
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StopsValue {
+    Number(f64),
+    Text(String),
+}
+
+/// Settings profile file, like:
+///
+/// ```yaml
+/// exposure_mode: aperture_priority
+/// metering_mode: matrix
+/// af_mode: single
+/// memo_holder: full
+/// exposure_compensation: -1/3
+/// ```
+///
+/// The settings that are left out are not changed. Only the settings that are mapped for the
+/// camera model can be used, which is only "memo_holder" on the F90x so far.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    exposure_mode: Option<String>,
+    shutter_speed: Option<String>,
+    aperture: Option<f32>,
+    exposure_compensation: Option<StopsValue>,
+    metering_mode: Option<String>,
+    af_mode: Option<String>,
+    film_advance_mode: Option<String>,
+    flash_sync_mode: Option<String>,
+    memo_holder: Option<String>,
+}
+
+/// One setting of a profile, with its value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProfileSetting {
+    ExposureMode(ExposureMode),
+    ShutterSpeed(ShutterSpeed),
+    Aperture(Aperture),
+    /// In stops.
+    ExposureCompensation(f64),
+    MeteringMode(MeteringMode),
+    AfMode(AfMode),
+    FilmAdvanceMode(FilmAdvanceMode),
+    FlashSyncMode(FlashSyncMode),
+    MemoHolder(MemoHolderSetting),
+}
+
+impl fmt::Display for ProfileSetting {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ProfileSetting::ExposureMode(mode) => write!(formatter, "{}", to_snake_case(mode)),
+            ProfileSetting::ShutterSpeed(speed) => write!(formatter, "{}", speed),
+            ProfileSetting::Aperture(aperture) => write!(formatter, "{}", aperture),
+            ProfileSetting::ExposureCompensation(stops) => write!(formatter, "{:+.2}", stops),
+            ProfileSetting::MeteringMode(mode) => write!(formatter, "{}", to_snake_case(mode)),
+            ProfileSetting::AfMode(mode) => write!(formatter, "{}", to_snake_case(mode)),
+            ProfileSetting::FilmAdvanceMode(mode) => write!(formatter, "{}", to_snake_case(mode)),
+            ProfileSetting::FlashSyncMode(mode) => write!(formatter, "{}", to_snake_case(mode)),
+            ProfileSetting::MemoHolder(setting) => write!(formatter, "{}", to_snake_case(setting)),
+        };
+    }
+}
+
+impl ProfileSetting {
+    pub fn get_name(&self) -> &'static str {
+        return match self {
+            ProfileSetting::ExposureMode(_) => "exposure_mode",
+            ProfileSetting::ShutterSpeed(_) => "shutter_speed",
+            ProfileSetting::Aperture(_) => "aperture",
+            ProfileSetting::ExposureCompensation(_) => "exposure_compensation",
+            ProfileSetting::MeteringMode(_) => "metering_mode",
+            ProfileSetting::AfMode(_) => "af_mode",
+            ProfileSetting::FilmAdvanceMode(_) => "film_advance_mode",
+            ProfileSetting::FlashSyncMode(_) => "flash_sync_mode",
+            ProfileSetting::MemoHolder(_) => "memo_holder",
+        };
+    }
+
+    fn is_mapped(&self, profile: &CameraProfile) -> bool {
+        return get_mapped_names(profile).contains(&self.get_name());
+    }
+
+    /// Reads the current value of the same setting from the camera.
+    fn read_current<T: CameraInterface>(&self, camera: &mut T, profile: &CameraProfile) -> Result<ProfileSetting> {
+        let mut settings = CameraSettings::new(camera, &profile.settings_map);
+        return Ok(match self {
+            ProfileSetting::ExposureMode(_) => ProfileSetting::ExposureMode(settings.get_exposure_mode()?),
+            ProfileSetting::ShutterSpeed(_) => ProfileSetting::ShutterSpeed(settings.get_shutter_speed()?),
+            ProfileSetting::Aperture(_) => ProfileSetting::Aperture(settings.get_aperture()?),
+            ProfileSetting::ExposureCompensation(_) => ProfileSetting::ExposureCompensation(settings.get_exposure_compensation()?),
+            ProfileSetting::MeteringMode(_) => ProfileSetting::MeteringMode(settings.get_metering_mode()?),
+            ProfileSetting::AfMode(_) => ProfileSetting::AfMode(settings.get_af_mode()?),
+            ProfileSetting::FilmAdvanceMode(_) => ProfileSetting::FilmAdvanceMode(settings.get_film_advance_mode()?),
+            ProfileSetting::FlashSyncMode(_) => ProfileSetting::FlashSyncMode(settings.get_flash_sync_mode()?),
+            ProfileSetting::MemoHolder(_) => {
+                ProfileSetting::MemoHolder(shooting_data::get_memo_holder_setting(camera, &profile.memory_map)?)
+            },
+        });
+    }
+
+    fn write<T: CameraInterface>(&self, camera: &mut T, profile: &CameraProfile) -> Result<()> {
+        let mut settings = CameraSettings::new(camera, &profile.settings_map);
+        return match *self {
+            ProfileSetting::ExposureMode(mode) => settings.set_exposure_mode(mode),
+            ProfileSetting::ShutterSpeed(speed) => settings.set_shutter_speed(speed),
+            ProfileSetting::Aperture(aperture) => settings.set_aperture(aperture),
+            ProfileSetting::ExposureCompensation(stops) => settings.set_exposure_compensation(stops),
+            ProfileSetting::MeteringMode(mode) => settings.set_metering_mode(mode),
+            ProfileSetting::AfMode(mode) => settings.set_af_mode(mode),
+            ProfileSetting::FilmAdvanceMode(mode) => settings.set_film_advance_mode(mode),
+            ProfileSetting::FlashSyncMode(mode) => settings.set_flash_sync_mode(mode),
+            ProfileSetting::MemoHolder(setting) => shooting_data::set_memo_holder_setting(camera, &profile.memory_map, setting),
+        };
+    }
+
+    /// Whether the camera value matches. The exposure compensation is compared with a tolerance, as
+    /// the profile can have thirds of a stop.
+    fn matches(&self, current: &ProfileSetting) -> bool {
+        return match (self, current) {
+            (ProfileSetting::ExposureCompensation(wanted), ProfileSetting::ExposureCompensation(current)) => {
+                (wanted - current).abs() < 0.05
+            },
+            _ => self == current,
+        };
+    }
+}
+
+/// Turns a variant name like "AperturePriority" into "aperture_priority".
+pub(crate) fn to_snake_case<V: fmt::Debug>(value: &V) -> String {
+    let mut name = String::new();
+    for character in format!("{:?}", value).chars() {
+        if character.is_ascii_uppercase() && !name.is_empty() {
+            name.push('_');
+        }
+        name.push(character.to_ascii_lowercase());
+    }
+    return name;
+}
+
+fn parse_name<V: Copy + fmt::Debug>(field: &str, value: &str, values: &[V]) -> Result<V> {
+    return values.iter()
+            .find(|known_value| to_snake_case(*known_value) == value)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<String> = values.iter().map(to_snake_case).collect();
+                anyhow!("Invalid {} \"{}\". Use one of: {}", field, value, names.join(", "))
+            });
+}
+
+/// Parses a shutter speed like "1/250", "2s" or "bulb".
+fn parse_shutter_speed(value: &str) -> Result<ShutterSpeed> {
+    let invalid = || anyhow!("Invalid shutter_speed \"{}\". Use for example \"1/250\", \"2s\" or \"bulb\".", value);
+    if value == "bulb" {
+        return Ok(ShutterSpeed::Bulb);
+    }
+    if let Some((numerator, denominator)) = value.split_once('/') {
+        return Ok(ShutterSpeed::Fraction(numerator.parse().map_err(|_| invalid())?, denominator.parse().map_err(|_| invalid())?));
+    }
+    let seconds = value.strip_suffix('s').unwrap_or(value);
+    return Ok(ShutterSpeed::Seconds(seconds.parse().map_err(|_| invalid())?));
+}
+
+/// Parses a YAML settings profile, see [ProfileFile]. The settings are in a fixed order.
+pub fn parse_profile(text: &str) -> Result<Vec<ProfileSetting>> {
+    let file: ProfileFile = serde_yaml::from_str(text)?;
+    let mut settings: Vec<ProfileSetting> = Vec::new();
+    if let Some(value) = &file.exposure_mode {
+        settings.push(ProfileSetting::ExposureMode(parse_name("exposure_mode", value, EXPOSURE_MODES)?));
+    }
+    if let Some(value) = &file.shutter_speed {
+        settings.push(ProfileSetting::ShutterSpeed(parse_shutter_speed(value)?));
+    }
+    if let Some(value) = file.aperture {
+        settings.push(ProfileSetting::Aperture(Aperture(value)));
+    }
+    if let Some(value) = &file.exposure_compensation {
+        let stops = match value {
+            StopsValue::Number(stops) => *stops,
+            StopsValue::Text(text) => bracketing::parse_stops(text)?,
+        };
+        settings.push(ProfileSetting::ExposureCompensation(stops));
+    }
+    if let Some(value) = &file.metering_mode {
+        settings.push(ProfileSetting::MeteringMode(parse_name("metering_mode", value, METERING_MODES)?));
+    }
+    if let Some(value) = &file.af_mode {
+        settings.push(ProfileSetting::AfMode(parse_name("af_mode", value, AF_MODES)?));
+    }
+    if let Some(value) = &file.film_advance_mode {
+        settings.push(ProfileSetting::FilmAdvanceMode(parse_name("film_advance_mode", value, FILM_ADVANCE_MODES)?));
+    }
+    if let Some(value) = &file.flash_sync_mode {
+        settings.push(ProfileSetting::FlashSyncMode(parse_name("flash_sync_mode", value, FLASH_SYNC_MODES)?));
+    }
+    if let Some(value) = &file.memo_holder {
+        settings.push(ProfileSetting::MemoHolder(parse_name("memo_holder", value, MEMO_HOLDER_SETTINGS)?));
+    }
+    return Ok(settings);
+}
+
+/// Reads a profile file. A name without an extension, like "studio", is read from "studio.yaml".
+pub fn load_profile(name: &str) -> Result<Vec<ProfileSetting>> {
+    let path = match Path::new(name).extension() {
+        None if !Path::new(name).exists() => format!("{}.yaml", name),
+        _ => name.to_string(),
+    };
+    let text = fs::read_to_string(&path).with_context(|| format!("Could not read the profile {}", path))?;
+    return parse_profile(&text).with_context(|| format!("Invalid profile {}", path));
+}
+
+/// A setting that has another value in the camera than in the profile.
+#[derive(Debug, PartialEq)]
+pub struct Drift {
+    pub wanted: ProfileSetting,
+    pub current: ProfileSetting,
+}
+
+/// Compares the profile with the current values, that are in the same order.
+pub fn get_drift(profile: &[ProfileSetting], current_values: &[ProfileSetting]) -> Vec<Drift> {
+    return profile.iter()
+            .zip(current_values)
+            .filter(|(wanted, current)| !wanted.matches(current))
+            .map(|(wanted, current)| Drift { wanted: *wanted, current: *current })
+            .collect();
+}
+
+/// Returns the names of the profile settings that can be used with the camera model. The memo
+/// holder setting is in the memory map of every model.
+fn get_mapped_names(profile: &CameraProfile) -> Vec<&'static str> {
+    let mut names = profile.settings_map.get_mapped_names();
+    names.push("memo_holder");
+    return names;
+}
+
+fn read_drift<T: CameraInterface>(session: &mut Session<T>, profile: &[ProfileSetting]) -> Result<(Vec<Drift>, &'static CameraProfile)> {
+    let camera_profile = session.start()?.get_profile()?;
+    let unmapped_names: Vec<&str> = profile.iter()
+            .filter(|setting| !setting.is_mapped(camera_profile))
+            .map(|setting| setting.get_name())
+            .collect();
+    if !unmapped_names.is_empty() {
+        return Err(anyhow!("{} not mapped for the {}. The settings that can be used are: {}",
+                           unmapped_names.join(", "), camera_profile.model_name, get_mapped_names(camera_profile).join(", ")));
+    }
+    let mut current_values: Vec<ProfileSetting> = Vec::new();
+    for setting in profile {
+        let current = setting.read_current(session.get_camera()?, camera_profile)
+                .with_context(|| format!("Could not read {}", setting.get_name()))?;
+        current_values.push(current);
+    }
+    return Ok((get_drift(profile, &current_values), camera_profile));
+}
+
+fn apply<T: CameraInterface>(
+        session: &mut Session<T>,
+        address_map: &AddressMap,
+        journal: &Journal,
+        profile: &[ProfileSetting]) -> Result<()> {
+    // Everything is read first, so that nothing is written if a setting is not supported.
+    let (drift, camera_profile) = read_drift(session, profile)?;
+    if drift.is_empty() {
+        println!("The camera already matches the profile.");
+        return Ok(());
+    }
+    let model = session.get_camera_info().ok_or(anyhow!("The session is not started"))?.model_name.clone();
+    for Drift { wanted, current } in &drift {
+        let mut camera = JournaledCamera::new(session.get_camera()?, journal, address_map, &model);
+        wanted.write(&mut camera, camera_profile)
+                .with_context(|| format!("Could not write {}", wanted.get_name()))?;
+        println!("{}: {} -> {}", wanted.get_name(), current, wanted);
+    }
+    println!("Applied and verified {} setting(s).", drift.len());
+    return Ok(());
+}
+
+/// Writes the settings of the profile that differ in the camera. Each write is verified by
+/// reading it back, and recorded in the journal.
+pub fn apply_profile(serial_device: &String, address_map: &AddressMap, journal: &Journal, profile_name: &str) -> Result<()> {
+    let profile = load_profile(profile_name)?;
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), true);
+    let result = apply(&mut session, address_map, journal, &profile);
+    session.close()?;
+    return result;
+}
+
+/// Prints the settings of the camera that differ from the profile. Fails if any does.
+pub fn check_profile(serial_device: &String, profile_name: &str) -> Result<()> {
+    let profile = load_profile(profile_name)?;
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), true);
+    let result = read_drift(&mut session, &profile);
+    session.close()?;
+    let (drift, _) = result?;
+
+    for Drift { wanted, current } in &drift {
+        println!("{}: {} in the camera, {} in the profile", wanted.get_name(), current, wanted);
+    }
+    if !drift.is_empty() {
+        return Err(anyhow!("{} setting(s) differ from the profile", drift.len()));
+    }
+    println!("The camera matches the profile.");
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use f90x_tool::camera_interface::models::F90X_PROFILE;
+    use f90x_tool::camera_interface::fake::FakeCamera;
+
+    #[test]
+    fn profile_should_be_parsed_in_a_fixed_order() {
+        let profile = parse_profile("memo_holder: full\nexposure_mode: aperture_priority\nexposure_compensation: -2/3\n").unwrap();
+        assert_eq!(vec![
+            ProfileSetting::ExposureMode(ExposureMode::AperturePriority),
+            ProfileSetting::ExposureCompensation(-2.0 / 3.0),
+            ProfileSetting::MemoHolder(MemoHolderSetting::Full),
+        ], profile);
+    }
+
+    #[test]
+    fn profile_values_should_be_parsed_by_type() {
+        let profile = parse_profile("shutter_speed: 1/250\naperture: 5.6\nexposure_compensation: 1\nflash_sync_mode: rear_curtain\n").unwrap();
+        assert_eq!(vec![
+            ProfileSetting::ShutterSpeed(ShutterSpeed::Fraction(1, 250)),
+            ProfileSetting::Aperture(Aperture(5.6)),
+            ProfileSetting::ExposureCompensation(1.0),
+            ProfileSetting::FlashSyncMode(FlashSyncMode::RearCurtain),
+        ], profile);
+        assert_eq!(ShutterSpeed::Seconds(2), parse_shutter_speed("2s").unwrap());
+        assert_eq!(ShutterSpeed::Bulb, parse_shutter_speed("bulb").unwrap());
+    }
+
+    #[test]
+    fn invalid_profiles_should_be_error() {
+        assert!(parse_profile("metering_mode: average\n").is_err());
+        assert!(parse_profile("iso: 400\n").is_err());
+        assert!(parse_profile("shutter_speed: fast\n").is_err());
+    }
+
+    #[test]
+    fn only_the_differing_settings_should_drift() {
+        let profile = parse_profile("af_mode: single\nexposure_compensation: -1/3\nmemo_holder: full\n").unwrap();
+        let current = vec![
+            ProfileSetting::ExposureCompensation(-1.0 / 3.0 + 0.001),
+            ProfileSetting::AfMode(AfMode::Continuous),
+            ProfileSetting::MemoHolder(MemoHolderSetting::Full),
+        ];
+        let drift = get_drift(&profile, &current);
+        assert_eq!(vec![Drift { wanted: ProfileSetting::AfMode(AfMode::Single), current: current[1] }], drift);
+        assert_eq!("af_mode", drift[0].wanted.get_name());
+        assert_eq!("continuous", drift[0].current.to_string());
+    }
+
+    #[test]
+    fn applied_settings_should_be_recorded_in_the_journal() {
+        let path = std::env::temp_dir().join(format!("f90x-tool-profile-test-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let journal = Journal::open(Some(&path.to_string_lossy().to_string()), "/dev/ttyUSB0").unwrap();
+        let address_map = AddressMap::load_with_defaults(None, &F90X_PROFILE).unwrap();
+        let mut memory = vec![0x00; 0x10000];
+        memory[0xFD40] = 0x45;
+        let mut session = Session::new(FakeCamera::new(memory), false);
+
+        apply(&mut session, &address_map, &journal, &parse_profile("memo_holder: full\n").unwrap()).unwrap();
+        assert_eq!(0x5F, session.get_camera().unwrap().memory[0xFD40]);
+        let journal_text = fs::read_to_string(&path).unwrap();
+        assert!(journal_text.contains("\"address\":64832,\"original_values\":[69],\"written_values\":[95]"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn settings_that_are_not_mapped_should_be_error_before_reading() {
+        let mut session = Session::new(FakeCamera::with_address_pattern(), false);
+        let profile = parse_profile("exposure_mode: manual
+memo_holder: full
+").unwrap();
+
+        let error = read_drift(&mut session, &profile).unwrap_err();
+        assert_eq!("exposure_mode not mapped for the F90X/N90S. The settings that can be used are: memo_holder", error.to_string());
+        assert!(session.get_camera().unwrap().get_reads().is_empty());
+    }
+}
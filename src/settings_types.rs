@@ -0,0 +1,66 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExposureMode {
+    ProgramAuto,
+    ShutterPriority,
+    AperturePriority,
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShutterSpeed {
+    /// Fraction of a second, e.g. `Fraction(1, 250)` for 1/250 s.
+    Fraction(u16, u16),
+    Seconds(u16),
+    Bulb,
+}
+
+/// Aperture as an f-number, e.g. `Aperture(5.6)` for f/5.6.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aperture(pub f32);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeteringMode {
+    Matrix,
+    CenterWeighted,
+    Spot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AfMode {
+    Single,
+    Continuous,
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilmAdvanceMode {
+    Single,
+    ContinuousLow,
+    ContinuousHigh,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlashSyncMode {
+    FrontCurtain,
+    SlowSync,
+    RearCurtain,
+    RedEyeReduction,
+}
+
+impl fmt::Display for ShutterSpeed {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ShutterSpeed::Fraction(numerator, denominator) => write!(formatter, "{}/{}", numerator, denominator),
+            ShutterSpeed::Seconds(seconds) => write!(formatter, "{}s", seconds),
+            ShutterSpeed::Bulb => write!(formatter, "bulb"),
+        };
+    }
+}
+
+impl fmt::Display for Aperture {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(formatter, "f/{}", self.0);
+    }
+}
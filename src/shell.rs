@@ -0,0 +1,103 @@
+use crate::address_map::AddressMap;
+use crate::journal::Journal;
+use crate::session_commands::{self, SessionCommand};
+use f90x_tool::camera_interface::{self, SerialCameraConnection};
+use f90x_tool::session::Session;
+
+use anyhow::Result;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Editor, Helper, Highlighter, Hinter, Validator};
+use std::path::PathBuf;
+
+/// Completes the command names, and the "fast" and "memo" arguments.
+#[derive(Helper, Hinter, Highlighter, Validator)]
+struct ShellHelper;
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _: &rustyline::Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let word_start = line.rfind(char::is_whitespace).map_or(0, |index| index + 1);
+        let word = &line[word_start..];
+        let previous_words: Vec<&str> = line[..word_start].split_whitespace().collect();
+
+        let candidates: &[&str] = match previous_words.as_slice() {
+            [] => session_commands::COMMAND_NAMES,
+            ["fast"] => &["on", "off"],
+            ["memo"] => &["status"],
+            _ => &[],
+        };
+        let matches = candidates.iter()
+                .filter(|candidate| candidate.starts_with(word))
+                .map(|candidate| candidate.to_string())
+                .collect();
+        return Ok((word_start, matches));
+    }
+}
+
+fn get_history_file() -> Option<PathBuf> {
+    return std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".f90x-tool-history"));
+}
+
+/// Runs an interactive shell that keeps one camera session open until exit.
+pub fn run_shell(
+        serial_device: &String,
+        address_map: &AddressMap,
+        journal: &Journal,
+        use_fast_session: bool) -> Result<()> {
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), use_fast_session);
+    let camera_info = session.start()?;
+    println!("Connected to {}. Type \"help\" for the commands.", camera_info.model_name);
+
+    let mut editor: Editor<ShellHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ShellHelper));
+    let history_file = get_history_file();
+    if let Some(history_file) = &history_file {
+        // The history file doesn't exist on the first run.
+        let _ = editor.load_history(history_file);
+    }
+
+    loop {
+        let line = match editor.readline("f90x> ") {
+            Ok(line) => line,
+            // Ctrl-C only discards the current line.
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                session.close()?;
+                return Err(error.into());
+            },
+        };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.as_str())?;
+        }
+
+        let command = match SessionCommand::parse(&line, address_map) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(error) => {
+                eprintln!("{:#}", error);
+                continue;
+            },
+        };
+        if command == SessionCommand::Exit {
+            break;
+        }
+        if let Err(error) = session_commands::execute(&mut session, address_map, journal, &command) {
+            eprintln!("Error: {:#}", error);
+            // A failed restart of an idle session leaves the session closed.
+            if session.get_camera_info().is_none() {
+                eprintln!("The session is closed. Type \"reconnect\" to start it again.");
+            }
+        }
+    }
+
+    if let Some(history_file) = &history_file {
+        editor.save_history(history_file)?;
+    }
+    return session.close();
+}
@@ -1,12 +1,17 @@
+use crate::caching_camera::CachingCamera;
 use crate::camera_interface::CameraInterface;
 use crate::camera_interface::messaging::CameraCommand;
+use crate::camera_interface::models::MemoryMap;
+use crate::camera_operations;
 
 use anyhow::{Result, anyhow};
 
 #[cfg(test)]
 use mockall::{predicate::*, Sequence};
 
-enum MemoHolderSetting {
+/// How much shooting data the memo holder stores for each frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoHolderSetting {
     DoNotStore,
     Minimum,
     Intermediate,
@@ -14,7 +19,7 @@ enum MemoHolderSetting {
 }
 
 impl MemoHolderSetting {
-    fn get_bytes_per_frame(&self) -> u8 {
+    pub fn get_bytes_per_frame(&self) -> u8 {
         match self {
             Self::DoNotStore   => 0,
             Self::Minimum      => 2,
@@ -24,23 +29,37 @@ impl MemoHolderSetting {
     }
 }
 
-struct MemoHolderInfo {
-    roll_id: u16,
-    bytes_to_read: u16,
+/// Flag of the memo holder setting value that is set when shooting data is stored.
+pub const MEMO_HOLDER_ENABLED_FLAG: u8 = 0x40;
+
+/// Memory values of the memo holder settings that store shooting data.
+pub const MEMO_HOLDER_SETTING_VALUES: &[(MemoHolderSetting, u8)] = &[
+    (MemoHolderSetting::Minimum, 0x45),
+    (MemoHolderSetting::Intermediate, 0x4E),
+    (MemoHolderSetting::Full, 0x5F),
+];
+
+#[derive(Debug)]
+pub struct MemoHolderInfo {
+    pub roll_id: u16,
+    pub bytes_to_read: u16,
 }
 
-struct RingBufferAddresses {
-    start: u16, // 0xFD00
-    end: u16,   // 0xFD02
+#[derive(Debug)]
+pub struct RingBufferAddresses {
+    pub start: u16, // 0xFD00 on F90x
+    pub end: u16,   // 0xFD02 on F90x
 }
-struct MemoHolderAddresses {
-    start: u16,              // 0xFD44
-    current_roll_start: u16, // 0xFD46
-    current: u16,            // 0xFD42
+#[derive(Debug)]
+pub struct MemoHolderAddresses {
+    pub start: u16,              // 0xFD44 on F90x
+    pub current_roll_start: u16, // 0xFD46 on F90x
+    pub current: u16,            // 0xFD42 on F90x
 }
 
-fn get_ring_buffer_addresses<T: CameraInterface>(camera: &mut T) -> Result<RingBufferAddresses> {
-    camera.send_command(&CameraCommand::ReadMemory { memory_space: 0, address: 0xFD00, length: 4})?;
+pub fn get_ring_buffer_addresses<T: CameraInterface>(camera: &mut T, memory_map: &MemoryMap) -> Result<RingBufferAddresses> {
+    let address = memory_map.ring_buffer_addresses;
+    camera.send_command(&CameraCommand::ReadMemory { memory_space: 0, address, length: 4})?;
     let data_packet = camera.expect_data_packet(4)?;
 
     let start = read_little_endian_u16(&data_packet.bytes, 0)?;
@@ -49,8 +68,9 @@ fn get_ring_buffer_addresses<T: CameraInterface>(camera: &mut T) -> Result<RingB
     return Ok(RingBufferAddresses { start, end });
 }
 
-fn get_memo_holder_addresses<T: CameraInterface>(camera: &mut T) -> Result<MemoHolderAddresses> {
-    camera.send_command(&CameraCommand::ReadMemory { memory_space: 0, address: 0xFD42, length: 6})?;
+pub fn get_memo_holder_addresses<T: CameraInterface>(camera: &mut T, memory_map: &MemoryMap) -> Result<MemoHolderAddresses> {
+    let address = memory_map.memo_holder_addresses;
+    camera.send_command(&CameraCommand::ReadMemory { memory_space: 0, address, length: 6})?;
     let data_packet = camera.expect_data_packet(6)?;
 
     let current = read_little_endian_u16(&data_packet.bytes, 0)?;
@@ -60,23 +80,32 @@ fn get_memo_holder_addresses<T: CameraInterface>(camera: &mut T) -> Result<MemoH
     return Ok(MemoHolderAddresses { start, current_roll_start, current });
 }
 
-fn get_memo_holder_setting<T: CameraInterface>(camera: &mut T) -> Result<MemoHolderSetting> {
-    camera.send_command(&CameraCommand::ReadMemory { memory_space: 0, address: 0xFD40, length: 1})?;
+pub fn get_memo_holder_setting<T: CameraInterface>(camera: &mut T, memory_map: &MemoryMap) -> Result<MemoHolderSetting> {
+    let address = memory_map.memo_holder_setting;
+    camera.send_command(&CameraCommand::ReadMemory { memory_space: 0, address, length: 1})?;
     let data_packet = camera.expect_data_packet(1)?;
     let value = data_packet.bytes.first().ok_or(anyhow!("Could not get the memory value"))?;
-    const MEMO_HOLDER_ENABLED_FLAG: u8 = 0x40;
     if (value & MEMO_HOLDER_ENABLED_FLAG) == 0x00 {
         return Ok(MemoHolderSetting::DoNotStore);
     }
-    match value {
-        &0x45 => Ok(MemoHolderSetting::Minimum),
-        &0x4E => Ok(MemoHolderSetting::Intermediate),
-        &0x5F => Ok(MemoHolderSetting::Full),
-        _ => Err(anyhow!("Unspecified memo holder setting value: {:02X?}", value))
-    }
+    return MEMO_HOLDER_SETTING_VALUES.iter()
+        .find(|(_, setting_value)| setting_value == value)
+        .map(|(setting, _)| *setting)
+        .ok_or(anyhow!("Unspecified memo holder setting value: {:02X?}", value));
 }
 
-fn get_memo_holder_info<T: CameraInterface>(camera: &mut T) -> Result<MemoHolderInfo> {
+/// Changes the memo holder setting. [MemoHolderSetting::DoNotStore] clears the enabled flag of
+/// the current value. The written value is verified by reading it back.
+pub fn set_memo_holder_setting<T: CameraInterface>(camera: &mut T, memory_map: &MemoryMap, setting: MemoHolderSetting) -> Result<()> {
+    let address = memory_map.memo_holder_setting;
+    let value = match MEMO_HOLDER_SETTING_VALUES.iter().find(|(known_setting, _)| *known_setting == setting) {
+        Some((_, value)) => *value,
+        None => camera_operations::read_memory(camera, 0, address, 1)?[0] & !MEMO_HOLDER_ENABLED_FLAG,
+    };
+    return camera_operations::write_memory_verified(camera, address, vec![value]);
+}
+
+pub fn get_memo_holder_info<T: CameraInterface>(camera: &mut T) -> Result<MemoHolderInfo> {
     camera.send_command(&CameraCommand::ReadMemoHolderInfo)?;
     let data_packet = camera.expect_data_packet(4)?;
     let bytes_to_read = read_little_endian_u16(&data_packet.bytes, 2)?;
@@ -87,10 +116,35 @@ fn get_memo_holder_info<T: CameraInterface>(camera: &mut T) -> Result<MemoHolder
     return Ok(MemoHolderInfo { roll_id, bytes_to_read });
 }
 
-fn has_finished_roll_to_read(addresses: &MemoHolderAddresses) -> bool {
+pub fn has_finished_roll_to_read(addresses: &MemoHolderAddresses) -> bool {
     return addresses.current_roll_start > addresses.start;
 }
 
+/// Everything that is known about the state of the memo holder.
+#[derive(Debug)]
+pub struct MemoHolderStatus {
+    pub setting: MemoHolderSetting,
+    pub ring_buffer: RingBufferAddresses,
+    pub addresses: MemoHolderAddresses,
+    pub info: MemoHolderInfo,
+}
+
+/// Reads the whole memo holder status. The nearby memory locations are read with one command.
+pub fn get_memo_holder_status<T: CameraInterface>(camera: &mut T, memory_map: &MemoryMap) -> Result<MemoHolderStatus> {
+    let mut camera = CachingCamera::new(camera);
+    camera.prefetch(0, &[
+        (memory_map.ring_buffer_addresses as usize)..(memory_map.ring_buffer_addresses as usize) + 4,
+        (memory_map.memo_holder_setting as usize)..(memory_map.memo_holder_setting as usize) + 1,
+        (memory_map.memo_holder_addresses as usize)..(memory_map.memo_holder_addresses as usize) + 6,
+    ])?;
+    return Ok(MemoHolderStatus {
+        setting: get_memo_holder_setting(&mut camera, memory_map)?,
+        ring_buffer: get_ring_buffer_addresses(&mut camera, memory_map)?,
+        addresses: get_memo_holder_addresses(&mut camera, memory_map)?,
+        info: get_memo_holder_info(&mut camera)?,
+    });
+}
+
 /// Read little endian u16 from the given vector.
 ///
 /// Returns error if the vector doesn't have enough bytes after the given index.
@@ -136,6 +190,7 @@ mod tests {
     use crate::camera_interface::messaging::CameraCommand;
     use crate::camera_interface::messaging::DataPacket;
     use crate::camera_interface::MockCameraInterface;
+    use crate::camera_interface::models::F90X_PROFILE;
 
     #[test]
     fn should_read_ring_buffer_addresses_correctly() {
@@ -151,7 +206,7 @@ mod tests {
                    .times(1)
                    .in_sequence(&mut sequence)
                    .returning(|_| Ok(DataPacket {bytes: vec![0xAB, 0xCD, 0x12, 0x34]}));
-        let result = get_ring_buffer_addresses(&mut mock_camera).unwrap();
+        let result = get_ring_buffer_addresses(&mut mock_camera, &F90X_PROFILE.memory_map).unwrap();
         assert_eq!(result.start, 0xCDAB);
         assert_eq!(result.end, 0x3412);
     }
@@ -170,7 +225,7 @@ mod tests {
                    .times(1)
                    .in_sequence(&mut sequence)
                    .returning(|_| Ok(DataPacket {bytes: vec![0x98, 0x76, 0xAB, 0xCD, 0x12, 0x34]}));
-        let result = get_memo_holder_addresses(&mut mock_camera).unwrap();
+        let result = get_memo_holder_addresses(&mut mock_camera, &F90X_PROFILE.memory_map).unwrap();
         assert_eq!(result.start, 0xCDAB);
         assert_eq!(result.current_roll_start, 0x3412);
         assert_eq!(result.current, 0x7698);
@@ -238,7 +293,7 @@ mod tests {
             .in_sequence(&mut sequence)
             .returning(move |_| Ok(DataPacket {bytes: vec![0x41]}));
 
-        let result = get_memo_holder_setting(&mut mock_camera);
+        let result = get_memo_holder_setting(&mut mock_camera, &F90X_PROFILE.memory_map);
         assert!(result.is_err());
     }
 
@@ -256,10 +311,60 @@ mod tests {
             .in_sequence(&mut sequence)
             .returning(move |_| Ok(DataPacket {bytes: vec![camera_value]}));
 
-        let result = get_memo_holder_setting(&mut mock_camera).unwrap();
+        let result = get_memo_holder_setting(&mut mock_camera, &F90X_PROFILE.memory_map).unwrap();
         assert_eq!(mem::discriminant(&expected_result), mem::discriminant(&result));
     }
 
+    fn expect_memo_holder_setting_write(mock_camera: &mut MockCameraInterface, sequence: &mut Sequence, value: u8) {
+        mock_camera.expect_send_command()
+            .with(eq(CameraCommand::WriteToMemory {address: 0xFD40, values: vec![value]}))
+            .times(1)
+            .in_sequence(sequence)
+            .returning(|_| Ok(()));
+        mock_camera.expect_expect_ok_response()
+            .times(1)
+            .in_sequence(sequence)
+            .returning(|| Ok(()));
+        mock_camera.expect_send_command()
+            .with(eq(CameraCommand::ReadMemory {memory_space: 0, address: 0xFD40, length: 1}))
+            .times(1)
+            .in_sequence(sequence)
+            .returning(|_| Ok(()));
+        mock_camera.expect_expect_data_packet()
+            .with(eq(1))
+            .times(1)
+            .in_sequence(sequence)
+            .returning(move |_| Ok(DataPacket {bytes: vec![value]}));
+    }
+
+    #[test]
+    fn should_write_memo_holder_setting_values() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        expect_memo_holder_setting_write(&mut mock_camera, &mut sequence, 0x4E);
+
+        set_memo_holder_setting(&mut mock_camera, &F90X_PROFILE.memory_map, MemoHolderSetting::Intermediate).unwrap();
+    }
+
+    #[test]
+    fn should_disable_memo_holder_by_clearing_the_flag() {
+        let mut sequence = Sequence::new();
+        let mut mock_camera = MockCameraInterface::new();
+        mock_camera.expect_send_command()
+            .with(eq(CameraCommand::ReadMemory {memory_space: 0, address: 0xFD40, length: 1}))
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_| Ok(()));
+        mock_camera.expect_expect_data_packet()
+            .with(eq(1))
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_| Ok(DataPacket {bytes: vec![0x5F]}));
+        expect_memo_holder_setting_write(&mut mock_camera, &mut sequence, 0x1F);
+
+        set_memo_holder_setting(&mut mock_camera, &F90X_PROFILE.memory_map, MemoHolderSetting::DoNotStore).unwrap();
+    }
+
     #[test]
     fn should_read_little_endian_u16_correctly() {
         let bytes: Vec<u8> = vec![0x12, 0x34, 0x56, 0x78];
@@ -0,0 +1,159 @@
+use crate::session_commands;
+use f90x_tool::camera_interface::{self, SerialCameraConnection};
+use f90x_tool::camera_status::{self, CameraStatus};
+use f90x_tool::session::Session;
+use f90x_tool::shooting_data;
+
+use anyhow::Result;
+use serde_json::{Value, json};
+
+fn format_debug<V: std::fmt::Debug>(value: V) -> String {
+    return format!("{:?}", value);
+}
+
+/// Formats the status as readable text, one value per line. The values that are not mapped for
+/// the model are left out.
+pub fn format_status_text(status: &CameraStatus) -> String {
+    let values = [
+        ("Frame count", status.frame_count.map(|count| count.to_string())),
+        ("Film loaded", status.film_loaded.map(|loaded| loaded.to_string())),
+        ("DX ISO", status.dx_iso.map(|iso| iso.to_string())),
+        ("CPU lens mounted", status.cpu_lens_mounted.map(|mounted| mounted.to_string())),
+        ("Focal length", status.focal_length_mm.map(|length| format!("{} mm", length))),
+        ("Maximum aperture", status.max_aperture.map(|aperture| aperture.to_string())),
+        ("Meter reading", status.meter_reading.map(|value| format!("{:02X}", value))),
+        ("Exposure mode", status.exposure_mode.map(format_debug)),
+        ("Shutter speed", status.shutter_speed.map(|speed| speed.to_string())),
+        ("Aperture", status.aperture.map(|aperture| aperture.to_string())),
+        ("Exposure compensation", status.exposure_compensation.map(|stops| format!("{:+.1} EV", stops))),
+        ("Metering mode", status.metering_mode.map(format_debug)),
+        ("AF mode", status.af_mode.map(format_debug)),
+        ("Film advance mode", status.film_advance_mode.map(format_debug)),
+        ("Flash sync mode", status.flash_sync_mode.map(format_debug)),
+    ];
+    let mut text = format!("Model: {}\n", status.model_name);
+    for (name, value) in values {
+        if let Some(value) = value {
+            text += &format!("{}: {}\n", name, value);
+        }
+    }
+    text += "Memo holder:\n";
+    text += &session_commands::format_memo_holder_status(&status.memo_holder)
+            .lines()
+            .map(|line| format!("  {}\n", line))
+            .collect::<String>();
+    return text;
+}
+
+/// Converts the status to JSON. The values that are not mapped for the model are left out.
+pub fn status_to_json(status: &CameraStatus) -> Value {
+    let memo_holder = &status.memo_holder;
+    let mut json = json!({
+        "model": status.model_name,
+        "frame_count": status.frame_count,
+        "film_loaded": status.film_loaded,
+        "dx_iso": status.dx_iso,
+        "cpu_lens_mounted": status.cpu_lens_mounted,
+        "focal_length_mm": status.focal_length_mm,
+        "max_aperture": status.max_aperture.map(|aperture| aperture.0),
+        "meter_reading": status.meter_reading,
+        "exposure_mode": status.exposure_mode.map(|mode| format!("{:?}", mode)),
+        "shutter_speed": status.shutter_speed.map(|speed| speed.to_string()),
+        "aperture": status.aperture.map(|aperture| aperture.0),
+        "exposure_compensation": status.exposure_compensation,
+        "metering_mode": status.metering_mode.map(|mode| format!("{:?}", mode)),
+        "af_mode": status.af_mode.map(|mode| format!("{:?}", mode)),
+        "film_advance_mode": status.film_advance_mode.map(|mode| format!("{:?}", mode)),
+        "flash_sync_mode": status.flash_sync_mode.map(|mode| format!("{:?}", mode)),
+        "memo_holder": {
+            "setting": format!("{:?}", memo_holder.setting),
+            "bytes_per_frame": memo_holder.setting.get_bytes_per_frame(),
+            "ring_buffer_start": memo_holder.ring_buffer.start,
+            "ring_buffer_end": memo_holder.ring_buffer.end,
+            "start": memo_holder.addresses.start,
+            "current_roll_start": memo_holder.addresses.current_roll_start,
+            "current": memo_holder.addresses.current,
+            "has_finished_roll_to_read": shooting_data::has_finished_roll_to_read(&memo_holder.addresses),
+            "roll_id": memo_holder.info.roll_id,
+            "bytes_to_read": memo_holder.info.bytes_to_read,
+        },
+    });
+    if let Value::Object(values) = &mut json {
+        values.retain(|_, value| !value.is_null());
+    }
+    return json;
+}
+
+/// Reads the camera status in one fast session and prints it as text or JSON.
+pub fn print_status_in_new_session(serial_device: &String, as_json: bool) -> Result<()> {
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), true);
+    let profile = session.start()?.get_profile()?;
+    let status = camera_status::get_camera_status(session.get_camera()?, profile);
+    session.close()?;
+
+    let status = status?;
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&status_to_json(&status))?);
+    } else {
+        print!("{}", format_status_text(&status));
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use f90x_tool::camera_settings::{FlashSyncMode, ShutterSpeed};
+    use f90x_tool::shooting_data::{MemoHolderAddresses, MemoHolderInfo, MemoHolderSetting, MemoHolderStatus, RingBufferAddresses};
+
+    fn test_status() -> CameraStatus {
+        return CameraStatus {
+            model_name: "F90X/N90S",
+            frame_count: Some(12),
+            film_loaded: Some(true),
+            dx_iso: None,
+            cpu_lens_mounted: None,
+            focal_length_mm: None,
+            max_aperture: None,
+            meter_reading: None,
+            exposure_mode: None,
+            shutter_speed: Some(ShutterSpeed::Fraction(1, 250)),
+            aperture: None,
+            exposure_compensation: Some(-1.0),
+            metering_mode: None,
+            af_mode: None,
+            film_advance_mode: None,
+            flash_sync_mode: Some(FlashSyncMode::RearCurtain),
+            memo_holder: MemoHolderStatus {
+                setting: MemoHolderSetting::Minimum,
+                ring_buffer: RingBufferAddresses { start: 0x1000, end: 0x2000 },
+                addresses: MemoHolderAddresses { current: 0x1000, start: 0x1000, current_roll_start: 0x1000 },
+                info: MemoHolderInfo { roll_id: 7, bytes_to_read: 0 },
+            },
+        };
+    }
+
+    #[test]
+    fn text_should_leave_out_values_that_are_not_mapped() {
+        let text = format_status_text(&test_status());
+        assert!(text.contains("Frame count: 12\n"));
+        assert!(!text.contains("DX ISO"));
+        assert!(text.contains("Shutter speed: 1/250\n"));
+        assert!(text.contains("Exposure compensation: -1.0 EV\n"));
+        assert!(!text.contains("Film advance mode"));
+        assert!(text.contains("Flash sync mode: RearCurtain\n"));
+        assert!(text.contains("  Roll: 7, bytes to read: 0\n"));
+    }
+
+    #[test]
+    fn json_should_leave_out_values_that_are_not_mapped() {
+        let json = status_to_json(&test_status());
+        assert_eq!(json!(12), json["frame_count"]);
+        assert!(json.get("dx_iso").is_none());
+        assert_eq!(json!("1/250"), json["shutter_speed"]);
+        assert_eq!(json!("RearCurtain"), json["flash_sync_mode"]);
+        assert_eq!(json!("Minimum"), json["memo_holder"]["setting"]);
+        assert_eq!(json!(0x1000), json["memo_holder"]["ring_buffer_start"]);
+    }
+}
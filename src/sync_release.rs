@@ -0,0 +1,103 @@
+use f90x_tool::camera_interface::CameraInterface;
+use f90x_tool::camera_interface::messaging::CameraCommand;
+use f90x_tool::camera_operations;
+
+use anyhow::{Result, anyhow};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Measured times of a release, from the start of the threads.
+struct ReleaseTiming {
+    serial_device: String,
+    sent: Duration,
+    result: Result<Duration>,
+}
+
+fn release_after_barrier<T: CameraInterface>(camera: &mut T, barrier: &Barrier, start: Instant) -> (Duration, Result<Duration>) {
+    barrier.wait();
+    let result = camera.send_command(&CameraCommand::Shoot);
+    let sent = start.elapsed();
+    let result = result.and_then(|_| camera.expect_ok_response()).map(|_| start.elapsed());
+    return (sent, result);
+}
+
+fn format_milliseconds(duration: Duration) -> String {
+    return format!("{:.1} ms", duration.as_secs_f64() * 1000.0);
+}
+
+/// Returns the time between the first and the last sent command.
+fn get_skew(timings: &[ReleaseTiming]) -> Duration {
+    let first = timings.iter().map(|timing| timing.sent).min().unwrap_or_default();
+    let last = timings.iter().map(|timing| timing.sent).max().unwrap_or_default();
+    return last - first;
+}
+
+/// Releases the shutters of several cameras at the same time.
+///
+/// The sessions are started first, and then each camera gets its own thread that sends the
+/// [CameraCommand::Shoot] command as soon as all the threads are ready. The time of sending the
+/// command and of receiving the OK response is printed for each camera.
+pub fn release_synchronized(serial_devices: &[String]) -> Result<()> {
+    let mut cameras = Vec::new();
+    for serial_device in serial_devices {
+        let camera = camera_operations::open_session(serial_device, false)?;
+        cameras.push((serial_device.clone(), camera));
+    }
+
+    let barrier = Arc::new(Barrier::new(cameras.len()));
+    let start = Instant::now();
+    let mut threads = Vec::new();
+    for (serial_device, mut camera) in cameras {
+        let barrier = barrier.clone();
+        threads.push(thread::spawn(move || {
+            let (sent, result) = release_after_barrier(&mut camera, &barrier, start);
+            return ReleaseTiming { serial_device, sent, result };
+        }));
+    }
+    let mut timings: Vec<ReleaseTiming> = Vec::new();
+    for thread in threads {
+        timings.push(thread.join().map_err(|_| anyhow!("A release thread panicked"))?);
+    }
+
+    // The times are printed from the first sent command.
+    let first_sent = timings.iter().map(|timing| timing.sent).min().unwrap_or_default();
+    let mut num_failed = 0;
+    for timing in &timings {
+        let sent = format_milliseconds(timing.sent - first_sent);
+        match &timing.result {
+            Ok(ok) => println!("{}: sent at +{}, OK at +{}", timing.serial_device, sent, format_milliseconds(*ok - first_sent)),
+            Err(error) => {
+                num_failed += 1;
+                println!("{}: sent at +{}, FAILED: {:#}", timing.serial_device, sent, error);
+            },
+        }
+    }
+    println!("Skew between the sent commands: {}", format_milliseconds(get_skew(&timings)));
+
+    if num_failed > 0 {
+        return Err(anyhow!("{} of {} releases failed", num_failed, timings.len()));
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(sent_micros: u64) -> ReleaseTiming {
+        let sent = Duration::from_micros(sent_micros);
+        return ReleaseTiming { serial_device: String::from("/dev/ttyUSB0"), sent, result: Ok(sent) };
+    }
+
+    #[test]
+    fn skew_should_be_between_first_and_last_command() {
+        assert_eq!(Duration::from_micros(250), get_skew(&[timing(300), timing(50), timing(120)]));
+        assert_eq!(Duration::ZERO, get_skew(&[timing(300)]));
+    }
+
+    #[test]
+    fn milliseconds_should_have_one_decimal() {
+        assert_eq!("1.3 ms", format_milliseconds(Duration::from_micros(1260)));
+    }
+}
@@ -0,0 +1,157 @@
+use f90x_tool::camera_interface::{self, CameraInterface, SerialCameraConnection};
+use f90x_tool::camera_operations;
+use f90x_tool::session::Session;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{Local, SecondsFormat};
+use std::fs::File;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Settings of a time-lapse run.
+pub struct TimelapseOptions {
+    pub interval: Duration,
+    pub count: u32,
+    pub focus_before_release: bool,
+    pub log_file: Option<String>,
+    pub use_fast_session: bool,
+}
+
+/// Returns the time of the given release. The times are counted from the start, so that a late
+/// release doesn't delay the following ones.
+fn get_release_time(start: Instant, interval: Duration, frame_index: u32) -> Instant {
+    return start + interval * frame_index;
+}
+
+/// Returns the index of the next frame to release, after the given time since the start. The
+/// frames whose release time has already passed, for example because the previous release took
+/// longer than the interval, are skipped.
+fn get_next_frame_index(elapsed: Duration, interval: Duration, next_frame_index: u32) -> u32 {
+    if interval.is_zero() {
+        return next_frame_index;
+    }
+    let first_upcoming_index = elapsed.as_nanos().div_ceil(interval.as_nanos());
+    return next_frame_index.max(u32::try_from(first_upcoming_index).unwrap_or(u32::MAX));
+}
+
+/// Releases the shutter, optionally triggering auto-focus first.
+pub fn release<T: CameraInterface>(session: &mut Session<T>, focus_before_release: bool) -> Result<()> {
+    if focus_before_release {
+        camera_operations::autofocus(session.get_camera()?).context("Auto-focus failed")?;
+    }
+    return camera_operations::release_shutter(session.get_camera()?);
+}
+
+/// Formats a line of the release log, as comma separated values.
+fn format_log_line(frame: u32, timestamp: &str, result: &Result<()>) -> String {
+    return match result {
+        Ok(()) => format!("{},{},ok", frame, timestamp),
+        Err(error) => format!("{},{},\"failed: {:#}\"", frame, timestamp, error).replace('\n', " "),
+    };
+}
+
+fn format_skipped_log_line(frame: u32, timestamp: &str) -> String {
+    return format!("{},{},skipped", frame, timestamp);
+}
+
+/// Releases the shutter `count` times with the given interval, keeping the camera awake in
+/// between. A failing release is reported, and the run continues with the next one. If a release
+/// takes longer than the interval, the frames that are already late are skipped instead of being
+/// released back to back.
+pub fn run_timelapse(serial_device: &String, options: &TimelapseOptions) -> Result<()> {
+    if options.count == 0 {
+        return Err(anyhow!("The count should be at least 1"));
+    }
+    let mut log_file = match &options.log_file {
+        Some(path) => {
+            let mut file = File::create(path).with_context(|| format!("Could not create the log file {}", path))?;
+            writeln!(file, "frame,host_time,result")?;
+            Some(file)
+        },
+        None => None,
+    };
+
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), options.use_fast_session);
+    session.start()?;
+
+    let mut failed_count = 0;
+    let mut skipped_count = 0;
+    let start = Instant::now();
+    let mut frame_index = 0;
+    while frame_index < options.count {
+        let frame = frame_index + 1;
+        session.wait_until(get_release_time(start, options.interval, frame_index));
+
+        let result = release(&mut session, options.focus_before_release);
+        let timestamp = Local::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+        match &result {
+            Ok(()) => println!("[{}] Frame {}/{} released", timestamp, frame, options.count),
+            Err(error) => {
+                failed_count += 1;
+                eprintln!("[{}] Frame {}/{} FAILED: {:#}", timestamp, frame, options.count, error);
+                // The camera might be left in the middle of a command, so a new session is started
+                // for the next frame.
+                if let Err(error) = session.start() {
+                    eprintln!("Could not start a new session: {:#}", error);
+                }
+            },
+        }
+        if let Some(file) = &mut log_file {
+            writeln!(file, "{}", format_log_line(frame, &timestamp, &result))?;
+        }
+
+        let next_frame_index = get_next_frame_index(start.elapsed(), options.interval, frame_index + 1).min(options.count);
+        for skipped_index in (frame_index + 1)..next_frame_index {
+            skipped_count += 1;
+            eprintln!("[{}] Frame {}/{} skipped, its release time has passed", timestamp, skipped_index + 1, options.count);
+            if let Some(file) = &mut log_file {
+                writeln!(file, "{}", format_skipped_log_line(skipped_index + 1, &timestamp))?;
+            }
+        }
+        if let Some(file) = &mut log_file {
+            file.flush()?;
+        }
+        frame_index = next_frame_index;
+    }
+
+    session.close()?;
+    if failed_count > 0 || skipped_count > 0 {
+        return Err(anyhow!("{} of {} releases failed and {} were skipped", failed_count, options.count, skipped_count));
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_times_should_not_drift() {
+        let start = Instant::now();
+        let interval = Duration::from_secs(30);
+        assert_eq!(start, get_release_time(start, interval, 0));
+        assert_eq!(start + Duration::from_secs(35 * 30), get_release_time(start, interval, 35));
+    }
+
+    #[test]
+    fn late_frames_should_be_skipped() {
+        let interval = Duration::from_secs(30);
+        assert_eq!(1, get_next_frame_index(Duration::from_secs(2), interval, 1));
+        assert_eq!(1, get_next_frame_index(Duration::from_secs(30), interval, 1));
+        // The first release took 95 seconds, so the frames at 30, 60 and 90 seconds are skipped.
+        assert_eq!(4, get_next_frame_index(Duration::from_secs(95), interval, 1));
+        assert_eq!(5, get_next_frame_index(Duration::from_secs(95), interval, 5));
+        assert_eq!(3, get_next_frame_index(Duration::from_secs(95), Duration::ZERO, 3));
+    }
+
+    #[test]
+    fn log_line_should_have_the_result() {
+        let timestamp = "2024-05-01T12:00:00.000+02:00";
+        assert_eq!("1,2024-05-01T12:00:00.000+02:00,ok", format_log_line(1, timestamp, &Ok(())));
+        let result = Err(anyhow!("Unexpected response"));
+        assert_eq!("2,2024-05-01T12:00:00.000+02:00,\"failed: Unexpected response\"",
+                   format_log_line(2, timestamp, &result));
+        assert_eq!("3,2024-05-01T12:00:00.000+02:00,skipped", format_skipped_log_line(3, timestamp));
+    }
+}
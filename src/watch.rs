@@ -0,0 +1,150 @@
+use crate::address_map::AddressMap;
+use crate::memory_diff;
+use crate::session_commands;
+use f90x_tool::caching_camera;
+use f90x_tool::camera_interface::{self, SerialCameraConnection};
+use f90x_tool::camera_operations;
+use f90x_tool::memory_dump::{self, ChangedRun, MemoryDump};
+use f90x_tool::session::Session;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{Local, SecondsFormat};
+use serde_json::json;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Parses an address range like "0xFD40-0xFD47" (inclusive end), "0xFD40+8" or "0xFD40". The
+/// addresses can also be names from the address map, like "memo.setting" for the whole location.
+pub fn parse_range(value: &str, address_map: &AddressMap, memory_space: u8) -> Result<Range<usize>> {
+    let range = if let Some((start, end)) = value.split_once('-') {
+        let start = address_map.resolve_in_space(start, memory_space)?;
+        let end = address_map.resolve_in_space(end, memory_space)?;
+        (start.address as usize)..(end.address as usize) + end.get_size()
+    } else if let Some((start, length)) = value.split_once('+') {
+        let start = address_map.resolve_in_space(start, memory_space)?;
+        let length: usize = session_commands::parse_number(length)?;
+        (start.address as usize)..(start.address as usize) + length
+    } else {
+        let resolved = address_map.resolve_in_space(value, memory_space)?;
+        (resolved.address as usize)..(resolved.address as usize) + resolved.get_size()
+    };
+    if range.is_empty() || range.end > 0x10000 {
+        return Err(anyhow!("Invalid address range \"{}\"", value));
+    }
+    return Ok(range);
+}
+
+fn format_change(timestamp: &str, run: &ChangedRun, memory_space: u8, address_map: &AddressMap, as_json: bool) -> String {
+    let range = run.start_address..run.start_address + run.old_values.len();
+    let names: Vec<&str> = address_map.find_overlapping(memory_space, &range).iter().map(|entry| entry.name.as_str()).collect();
+    if as_json {
+        return json!({
+            "time": timestamp,
+            "space": memory_space,
+            "address": run.start_address,
+            "old": run.old_values,
+            "new": run.new_values,
+            "names": names,
+        }).to_string();
+    }
+    let mut line = format!("[{}] {:04X}: {} -> {}", timestamp, run.start_address,
+                           memory_diff::format_values(&run.old_values), memory_diff::format_values(&run.new_values));
+    if !names.is_empty() {
+        line += &format!(" ({})", names.join(", "));
+    }
+    return line;
+}
+
+/// Polls the given address ranges with the given interval in a fast session, and prints the
+/// bytes that changed. Runs until Ctrl-C.
+pub fn watch_memory(
+        serial_device: &String,
+        memory_space: u8,
+        ranges: &[String],
+        interval: Duration,
+        address_map: &AddressMap,
+        as_json: bool) -> Result<()> {
+    let ranges = ranges.iter()
+            .map(|range| parse_range(range, address_map, memory_space))
+            .collect::<Result<Vec<Range<usize>>>>()?;
+    let ranges = caching_camera::coalesce_ranges(ranges, 0);
+    let stopped = Arc::new(AtomicBool::new(false));
+    let handler_flag = stopped.clone();
+    ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst))
+            .context("Could not set the Ctrl-C handler")?;
+
+    let serial = camera_interface::open_connection(serial_device, camera_interface::DEFAULT_TIMEOUT)?;
+    let mut session = Session::new(SerialCameraConnection::new(serial), true);
+    session.start()?;
+    eprintln!("Watching {} range(s), press Ctrl-C to stop.", ranges.len());
+
+    let mut previous_dumps: Vec<Option<MemoryDump>> = ranges.iter().map(|_| None).collect();
+    while !stopped.load(Ordering::SeqCst) {
+        let next_poll = Instant::now() + interval;
+        for (range, previous_dump) in ranges.iter().zip(previous_dumps.iter_mut()) {
+            let data = match session.get_camera()
+                    .and_then(|camera| camera_operations::read_memory_range(camera, memory_space, range.start as u16, range.len())) {
+                Ok(data) => data,
+                Err(error) => {
+                    eprintln!("Could not read {:04X}-{:04X}: {:#}", range.start, range.end - 1, error);
+                    // The camera might be left in the middle of a command.
+                    let _ = session.start();
+                    continue;
+                },
+            };
+            let dump = MemoryDump { memory_space, start_address: range.start as u16, data, unreadable_ranges: Vec::new() };
+            if let Some(previous_dump) = previous_dump {
+                let timestamp = Local::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+                for run in memory_dump::diff_dumps(previous_dump, &dump)? {
+                    println!("{}", format_change(&timestamp, &run, memory_space, address_map, as_json));
+                }
+            }
+            *previous_dump = Some(dump);
+        }
+        session.wait_until(next_poll);
+    }
+    return session.close();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use f90x_tool::camera_interface::models::F90X_PROFILE;
+
+    #[test]
+    fn ranges_should_be_parsed() {
+        let address_map = AddressMap::default();
+        assert_eq!(0xFD40..0xFD48, parse_range("0xFD40-0xFD47", &address_map, 0).unwrap());
+        assert_eq!(0xFD40..0xFD48, parse_range("0xFD40+8", &address_map, 0).unwrap());
+        assert_eq!(0xFD40..0xFD41, parse_range("0xFD40", &address_map, 0).unwrap());
+        assert!(parse_range("0xFD47-0xFD40", &address_map, 0).is_err());
+        assert!(parse_range("0xFFFF+2", &address_map, 0).is_err());
+        assert!(parse_range("0xFD40+0", &address_map, 0).is_err());
+    }
+
+    #[test]
+    fn ranges_should_be_parsed_with_names() {
+        let address_map = AddressMap::load_with_defaults(None, &F90X_PROFILE).unwrap();
+        assert_eq!(0xFD40..0xFD41, parse_range("memo.setting", &address_map, 0).unwrap());
+        assert_eq!(0xFD42..0xFD44, parse_range("memo.current", &address_map, 0).unwrap());
+        assert_eq!(0xFD40..0xFD48, parse_range("memo.setting-memo.current_roll_start", &address_map, 0).unwrap());
+        assert_eq!(0xFD40..0xFD48, parse_range("memo.setting+8", &address_map, 0).unwrap());
+        assert!(parse_range("memo.setting", &address_map, 1).is_err());
+    }
+
+    #[test]
+    fn changes_should_be_formatted_as_text_or_json() {
+        let address_map = AddressMap::parse("[[address]]\nname = \"memo.setting\"\naddress = 0xFD40\n").unwrap();
+        let run = ChangedRun { start_address: 0xFD40, old_values: vec![0x45], new_values: vec![0x5F] };
+        let timestamp = "2024-06-21T12:00:00.000+02:00";
+        assert_eq!("[2024-06-21T12:00:00.000+02:00] FD40: 45 -> 5F (memo.setting)",
+                   format_change(timestamp, &run, 0, &address_map, false));
+
+        let line: serde_json::Value = serde_json::from_str(&format_change(timestamp, &run, 0, &address_map, true)).unwrap();
+        assert_eq!(json!(0xFD40), line["address"]);
+        assert_eq!(json!([0x5F]), line["new"]);
+        assert_eq!(json!(["memo.setting"]), line["names"]);
+    }
+}